@@ -1,30 +1,74 @@
+use std::num::NonZero;
 use std::path::PathBuf;
 use std::str::FromStr;
 
-use clap::{ArgGroup, Parser};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use spreet::{Optlevel, SdfOptions};
 
 /// Container for Spreet's command-line arguments.
+// Without a subcommand, Spreet creates a spritesheet from a directory of SVGs.
 #[derive(Parser)]
 #[command(version, about)]
-#[command(group(ArgGroup::new("pixel_ratio").args(&["ratio", "retina"])))]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Cli {
-    /// A directory of SVGs to include in the spritesheet
-    #[arg(value_parser = is_dir)]
-    pub input: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub spritesheet: SpritesheetArgs,
+    /// Set the number of threads used to render sprites, instead of one per CPU
+    #[arg(short, long, global = true, value_name = "THREADS")]
+    pub jobs: Option<NonZero<usize>>,
+}
+
+/// Arguments describing a spritesheet to create from a directory of SVGs.
+#[derive(Args)]
+#[command(group(ArgGroup::new("pixel_ratio").args(&["ratio", "retina", "ratios"])))]
+#[command(group(ArgGroup::new("sprite_filter").args(&["style", "allowlist"])))]
+pub struct SpritesheetArgs {
+    /// Directories of SVGs to include in the spritesheet, each optionally given as `PREFIX=INPUT`
+    /// to add `PREFIX/` to the names of its sprites
+    #[arg(required = true, value_parser = is_prefixed_dir)]
+    pub input: Vec<PrefixedPath>,
     /// Name of the file in which to save the spritesheet
-    pub output: String,
+    #[arg(required = true)]
+    pub output: Option<String>,
     /// Set the output pixel ratio
     #[arg(short, long, default_value_t = 1, value_parser = is_positive)]
     pub ratio: u8,
     /// Set the pixel ratio to 2 (equivalent to `--ratio=2`)
     #[arg(long)]
     pub retina: bool,
+    /// Output one spritesheet per pixel ratio, adding an `@2x`-style suffix to the file names
+    #[arg(long, value_name = "RATIOS", value_delimiter = ',', value_parser = is_positive)]
+    pub ratios: Option<Vec<u8>>,
     /// Store only unique images in the spritesheet, and map them to multiple names
     #[arg(long)]
     pub unique: bool,
+    /// List pairs of images that differ by no more than this proportion of their pixels' colour
+    /// (0–1, 0.01 if not given), but aren't identical, so that they can be merged into one image
+    #[arg(long, value_name = "DIFFERENCE", num_args = 0..=1, default_missing_value = "0.01", value_parser = is_proportion)]
+    pub near_duplicates: Option<f32>,
     /// Include images in sub-directories
     #[arg(long)]
     pub recursive: bool,
+    /// Include only images matching this pattern, in `.gitignore` syntax (can be repeated)
+    #[arg(long, value_name = "PATTERN")]
+    pub include: Vec<String>,
+    /// Leave out images and directories matching this pattern, in `.gitignore` syntax (can be
+    /// repeated)
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+    /// Include PNG and WebP images, with an optional `@2x`-style pixel ratio suffix, as well as
+    /// SVGs
+    #[arg(long)]
+    pub raster: bool,
+    /// What to do when more than one image has the same sprite name: exit with an error, warn and
+    /// keep the first image, or keep the first image
+    #[arg(long, value_enum, default_value_t = NameConflictPolicy::Error)]
+    pub on_conflict: NameConflictPolicy,
+    /// Leave out images that can't be loaded, instead of failing
+    #[arg(long)]
+    pub skip_invalid: bool,
     /// Crop rendered images to remove transparent pixels around the edges
     #[arg(long)]
     pub crop: bool,
@@ -34,6 +78,87 @@ pub struct Cli {
     /// Add pixel spacing between sprites
     #[arg(long, default_value_t = 0, value_parser = is_non_negative)]
     pub spacing: u8,
+    #[command(flatten)]
+    pub output_options: OutputArgs,
+    /// Output a spritesheet using a signed distance field for each sprite
+    #[arg(long)]
+    pub sdf: bool,
+    /// Set the transparent buffer added to each side of an SDF sprite, in pixels at a ratio of 1
+    #[arg(long, value_name = "PIXELS", default_value_t = 3, requires("sdf"), value_parser = is_non_negative)]
+    pub sdf_buffer: u8,
+    /// Set the maximum distance encoded in an SDF sprite, in pixels at a ratio of 1
+    #[arg(long, value_name = "PIXELS", default_value_t = 8, requires("sdf"), value_parser = is_positive)]
+    pub sdf_radius: u8,
+    /// Set the proportion of an SDF sprite's distance range that lies inside its edges (0–1)
+    #[arg(long, value_name = "CUTOFF", default_value_t = 0.25, requires("sdf"), value_parser = is_fraction)]
+    pub sdf_cutoff: f64,
+    /// Keep sprites at their positions in the existing index file, packing only new or resized ones
+    #[arg(long, conflicts_with("max_size"))]
+    pub incremental: bool,
+    /// Rebuild the spritesheet whenever an image in the input directories changes, until stopped
+    #[arg(long)]
+    pub watch: bool,
+    /// Save rendered SVG images in this directory, and use them in later builds instead of
+    /// rendering images that haven't changed
+    #[arg(long, value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+    /// Split the sprites across several spritesheets no wider or higher than this, named with a
+    /// `-0`-style page suffix
+    #[arg(long, value_name = "PIXELS", value_parser = is_positive_size)]
+    pub max_size: Option<u32>,
+    /// Save one index file for all the pages, recording each sprite's page, instead of one per page
+    #[arg(long, requires("max_size"), conflicts_with("simple_index_file"))]
+    pub combined_index: bool,
+    /// Choose the algorithm that arranges the sprites in the spritesheet, and report how much of
+    /// the spritesheet they fill
+    #[arg(long, value_enum)]
+    pub packer: Option<PackerKind>,
+    /// Allow spritesheets of any width and height, instead of only powers of two
+    #[arg(long)]
+    pub any_size: bool,
+    /// Make the spritesheet square
+    #[arg(long)]
+    pub square: bool,
+    /// Make the spritesheet exactly this many pixels wide
+    #[arg(long, value_name = "PIXELS", value_parser = is_positive_size, conflicts_with("any_size"))]
+    pub width: Option<u32>,
+    /// Include only the images used by a MapLibre or Mapbox style JSON file
+    #[arg(long, value_name = "FILE", value_parser = is_file)]
+    pub style: Option<PathBuf>,
+    /// Include only the images named in a file, one per line
+    #[arg(long, value_name = "FILE", value_parser = is_file)]
+    pub allowlist: Option<PathBuf>,
+    /// Fail if the style or allowlist uses an image that isn't in the input directories, instead of
+    /// warning about it
+    #[arg(long, requires("sprite_filter"))]
+    pub strict: bool,
+}
+
+impl SpritesheetArgs {
+    /// The signed distance field options selected by `--sdf-buffer`, `--sdf-radius` and
+    /// `--sdf-cutoff`.
+    pub fn sdf_options(&self) -> SdfOptions {
+        SdfOptions {
+            buffer: self.sdf_buffer,
+            radius: self.sdf_radius,
+            cutoff: self.sdf_cutoff,
+        }
+    }
+}
+
+/// Algorithms for packing sprites into a spritesheet.
+#[derive(Clone, Copy, ValueEnum)]
+// Variants are left undocumented so that `--help` lists them on one line.
+pub enum PackerKind {
+    Crunch,
+    Shelf,
+    Skyline,
+    MaxRects,
+}
+
+/// Arguments controlling how spritesheets and index files are saved.
+#[derive(Args)]
+pub struct OutputArgs {
     /// Specify the PNG optimization level (0–6, default: 2)
     #[arg(long, group = "optlevel", value_name = "LEVEL", value_parser = is_max_6)]
     pub oxipng: Option<u8>,
@@ -46,9 +171,227 @@ pub struct Cli {
     #[arg(long)]
     /// Output only x, y, width, and height to the JSON index file
     pub simple_index_file: bool,
-    /// Output a spritesheet using a signed distance field for each sprite
+    /// Save a report listing every sprite alongside each spritesheet, as HTML and/or Markdown
+    #[arg(long, value_name = "FORMATS", value_delimiter = ',', value_enum)]
+    pub report: Vec<ReportFormat>,
+}
+
+/// Formats for the sprite reports saved with `--report`.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+// Variants are left undocumented so that `--help` lists them on one line.
+pub enum ReportFormat {
+    Html,
+    Markdown,
+}
+
+impl OutputArgs {
+    /// The PNG optimization level selected by `--oxipng` or `--zopfli`.
+    pub fn optlevel(&self) -> Optlevel {
+        match (self.oxipng, self.zopfli) {
+            (None, None) => Optlevel::default(),
+            (Some(level), None) => Optlevel::Oxipng { level },
+            (None, Some(iterations)) => Optlevel::Zopfli {
+                iterations: NonZero::new(iterations).unwrap(),
+            },
+            (Some(_), Some(_)) => unreachable!(),
+        }
+    }
+}
+
+/// Spreet's subcommands.
+#[derive(Subcommand)]
+pub enum Command {
+    /// Create the spritesheets described by a config file
+    Build(BuildArgs),
+    /// Extract the sprites from a spritesheet into individual PNG images
+    Extract(ExtractArgs),
+    /// Merge several spritesheets into one, without needing their original SVGs
+    Merge(MergeArgs),
+    /// Compare two spritesheets, exiting with status 1 if their sprites differ
+    Diff(DiffArgs),
+    /// Check a spritesheet's index file for problems, printing them as JSON
+    Validate(ValidateArgs),
+    /// Compare the icons used by a MapLibre or Mapbox style with the sprites that are available
+    CheckStyle(CheckStyleArgs),
+    /// Preview a spritesheet in a web browser, rebuilding it whenever an image changes
+    Serve(ServeArgs),
+}
+
+/// Arguments for the `build` subcommand.
+#[derive(Args)]
+pub struct BuildArgs {
+    /// A TOML or JSON file describing the spritesheets to create
+    #[arg(default_value = "spreet.toml", value_parser = is_file)]
+    pub config: PathBuf,
+}
+
+/// Arguments for the `extract` subcommand.
+#[derive(Args)]
+pub struct ExtractArgs {
+    /// The spritesheet PNG image to extract sprites from
+    #[arg(value_parser = is_file)]
+    pub spritesheet: PathBuf,
+    /// A directory in which to save the sprites
+    pub output: PathBuf,
+    /// The spritesheet's JSON index file [default: the spritesheet with a `.json` extension]
+    #[arg(long, value_parser = is_file)]
+    pub index: Option<PathBuf>,
+}
+
+/// Arguments for the `merge` subcommand.
+#[derive(Args)]
+pub struct MergeArgs {
+    /// Spritesheet PNG images to merge, each optionally given as `PREFIX=SPRITESHEET` to add
+    /// `PREFIX/` to the names of its sprites. Index files are read from the spritesheets' paths
+    /// with a `.json` extension
+    #[arg(required = true, value_name = "SPRITESHEETS", value_parser = is_prefixed_file)]
+    pub spritesheets: Vec<PrefixedPath>,
+    /// Name of the file in which to save the merged spritesheet
+    pub output: String,
+    /// Resample all sprites to this pixel ratio [default: the sprites' own pixel ratio]
+    #[arg(short, long, value_parser = is_positive)]
+    pub ratio: Option<u8>,
+    /// Store only unique images in the spritesheet, and map them to multiple names
     #[arg(long)]
-    pub sdf: bool,
+    pub unique: bool,
+    /// Add pixel spacing between sprites
+    #[arg(long, default_value_t = 0, value_parser = is_non_negative)]
+    pub spacing: u8,
+    /// What to do when more than one spritesheet has a sprite with the same name
+    #[arg(long, value_enum, default_value_t = ConflictPolicy::Error)]
+    pub on_conflict: ConflictPolicy,
+    #[command(flatten)]
+    pub output_options: OutputArgs,
+}
+
+/// Arguments for the `diff` subcommand.
+#[derive(Args)]
+pub struct DiffArgs {
+    /// The old spritesheet PNG image. Its index file is read from its path with a `.json` extension
+    #[arg(value_parser = is_file)]
+    pub old: PathBuf,
+    /// The new spritesheet PNG image. Its index file is read from its path with a `.json` extension
+    #[arg(value_parser = is_file)]
+    pub new: PathBuf,
+    /// Ignore changes to a sprite's pixels that are no more than this different (0–1)
+    #[arg(long, value_name = "DIFFERENCE", default_value_t = 0.0, value_parser = is_proportion)]
+    pub threshold: f32,
+    /// Save an image showing the old and new versions of each sprite that differs
+    #[arg(long, value_name = "FILE")]
+    pub diff_image: Option<PathBuf>,
+}
+
+/// Arguments for the `validate` subcommand.
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// The spritesheet PNG image to validate. Its pixel ratio is read from its `@2x`-style suffix
+    #[arg(value_parser = is_file)]
+    pub spritesheet: PathBuf,
+    /// The spritesheet's JSON index file [default: the spritesheet with a `.json` extension]
+    #[arg(long, value_parser = is_file)]
+    pub index: Option<PathBuf>,
+}
+
+/// Arguments for the `check-style` subcommand.
+#[derive(Args)]
+#[command(group(ArgGroup::new("sprites").args(&["input", "index"]).required(true)))]
+pub struct CheckStyleArgs {
+    /// The style JSON file to check
+    #[arg(value_parser = is_file)]
+    pub style: PathBuf,
+    /// A directory of SVGs that the spritesheet will be created from, optionally given as
+    /// `PREFIX=INPUT` to add `PREFIX/` to the names of its sprites (can be repeated)
+    #[arg(long, value_parser = is_prefixed_dir)]
+    pub input: Vec<PrefixedPath>,
+    /// An existing spritesheet's JSON index file
+    #[arg(long, value_parser = is_file)]
+    pub index: Option<PathBuf>,
+    /// Include images in sub-directories of the input directories
+    #[arg(long, requires("input"))]
+    pub recursive: bool,
+    /// Include only images matching this pattern, in `.gitignore` syntax (can be repeated)
+    #[arg(long, value_name = "PATTERN", requires("input"))]
+    pub include: Vec<String>,
+    /// Leave out images and directories matching this pattern, in `.gitignore` syntax (can be
+    /// repeated)
+    #[arg(long, value_name = "PATTERN", requires("input"))]
+    pub exclude: Vec<String>,
+    /// Include PNG and WebP images in the input directories, as well as SVGs
+    #[arg(long, requires("input"))]
+    pub raster: bool,
+}
+
+/// How to resolve sprites with the same name.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Exit with an error
+    Error,
+    /// Keep the first sprite with the name
+    First,
+    /// Keep the last sprite with the name
+    Last,
+}
+
+/// How to resolve input images with the same sprite name.
+#[derive(Clone, Copy, ValueEnum)]
+// Variants are left undocumented so that `--help` lists them on one line.
+pub enum NameConflictPolicy {
+    Error,
+    Warn,
+    First,
+}
+
+/// Arguments for the `serve` subcommand.
+#[derive(Args)]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub spritesheet: SpritesheetArgs,
+    /// The port to serve the preview from, on localhost
+    #[arg(long, default_value_t = 8080)]
+    pub port: u16,
+}
+
+/// A file path with an optional prefix for the names of the sprites read from it.
+#[derive(Clone)]
+pub struct PrefixedPath {
+    pub prefix: Option<String>,
+    pub path: PathBuf,
+}
+
+/// Clap validator to ensure that a string is an existing file, optionally preceded by `PREFIX=`.
+fn is_prefixed_file(s: &str) -> Result<PrefixedPath, String> {
+    prefixed_path(s, is_file)
+}
+
+/// Clap validator to ensure that a string is an existing directory, optionally preceded by
+/// `PREFIX=`.
+fn is_prefixed_dir(s: &str) -> Result<PrefixedPath, String> {
+    prefixed_path(s, is_dir)
+}
+
+/// Split a string into an optional `PREFIX=` and a path, which is checked with `validate`. A string
+/// that's a valid path as a whole is never split, even if it contains `=`.
+fn prefixed_path(
+    s: &str,
+    validate: fn(&str) -> Result<PathBuf, String>,
+) -> Result<PrefixedPath, String> {
+    let (prefix, path) = match s.split_once('=') {
+        Some((prefix, path)) if !prefix.is_empty() && validate(s).is_err() => (Some(prefix), path),
+        _ => (None, s),
+    };
+    Ok(PrefixedPath {
+        prefix: prefix.map(String::from),
+        path: validate(path)?,
+    })
+}
+
+/// Clap validator to ensure that a string is an existing file.
+fn is_file(p: &str) -> Result<PathBuf, String> {
+    if PathBuf::from(p).is_file() {
+        Ok(p.into())
+    } else {
+        Err(String::from("must be an existing file"))
+    }
 }
 
 /// Clap validator to ensure that a string is an existing directory.
@@ -70,13 +413,39 @@ fn is_positive(s: &str) -> Result<u8, String> {
         })
 }
 
+/// Clap validator to ensure that a size in pixels parsed from a string is greater than zero.
+fn is_positive_size(s: &str) -> Result<u32, String> {
+    u32::from_str(s)
+        .map_err(|e| e.to_string())
+        .and_then(|result| match result {
+            i if i > 0 => Ok(result),
+            _ => Err(String::from("must be greater than zero")),
+        })
+}
+
 /// Clap validator to ensure that an unsigned integer parsed from a string is non-negative.
 fn is_non_negative(s: &str) -> Result<u8, String> {
-    u8::from_str(s)
-        .map_err(|_| String::from("must be a non-negative number"))
-        .and_then(|result| {
-            // u8 is inherently non-negative, so we just need to validate parsing
-            Ok(result)
+    // u8 is inherently non-negative, so we just need to validate parsing
+    u8::from_str(s).map_err(|_| String::from("must be a non-negative number"))
+}
+
+/// Clap validator to ensure that a float parsed from a string is between zero and one, exclusive.
+fn is_fraction(s: &str) -> Result<f64, String> {
+    f64::from_str(s)
+        .map_err(|e| e.to_string())
+        .and_then(|result| match result {
+            f if f > 0.0 && f < 1.0 => Ok(result),
+            _ => Err(String::from("must be a number between 0 and 1")),
+        })
+}
+
+/// Clap validator to ensure that a float parsed from a string is between zero and one, inclusive.
+fn is_proportion(s: &str) -> Result<f32, String> {
+    f32::from_str(s)
+        .map_err(|e| e.to_string())
+        .and_then(|result| match result {
+            f if (0.0..=1.0).contains(&f) => Ok(result),
+            _ => Err(String::from("must be a number from 0 to 1")),
         })
 }
 
@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::cli::{Cli, SpritesheetArgs};
+
+/// Options whose values are file paths.
+const PATH_OPTIONS: [&str; 3] = ["style", "allowlist", "cache-dir"];
+
+/// A config file describing several spritesheet builds, for the `build` subcommand.
+///
+/// Config files are written in TOML, or in JSON if the file name has a `.json` extension:
+///
+/// ```toml
+/// [builds.light]
+/// input = "icons/light"
+/// output = "dist/light"
+/// ratios = [1, 2]
+/// unique = true
+/// minify-index-file = true
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The builds in the config file, keyed by name.
+    pub builds: BTreeMap<String, BuildConfig>,
+}
+
+/// A single spritesheet build in a config file.
+///
+/// Apart from `input` and `output`, each option has the same name and meaning as one of Spreet's
+/// command-line arguments, without the leading `--`. Flags are set with `true` or `false`, and
+/// lists (such as `ratios`) are given as arrays. Files named by the `style`, `allowlist` and
+/// `cache-dir` options are relative to the config file.
+#[derive(Deserialize)]
+pub struct BuildConfig {
+    /// One or more directories of images, relative to the config file, each optionally given as
+    /// `PREFIX=INPUT`.
+    pub input: BuildInput,
+    /// Name of the file in which to save the spritesheet, relative to the config file.
+    pub output: PathBuf,
+    /// Command-line arguments for the build.
+    #[serde(flatten)]
+    pub options: BTreeMap<String, Value>,
+}
+
+/// The input directories of a build: either a single directory, or an array of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum BuildInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Config {
+    /// Load a config file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&contents).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&contents).map_err(|e| e.to_string().trim_end().to_string())
+        }
+    }
+}
+
+impl BuildConfig {
+    /// Convert the build to the equivalent command-line arguments, so that it's validated and run
+    /// in exactly the same way as a spritesheet built from the command line. Relative paths are
+    /// resolved from `base_dir`, the directory containing the config file.
+    pub fn to_args(&self, base_dir: &Path) -> Result<SpritesheetArgs, String> {
+        // Make sure that an input directory isn't mistaken for a subcommand.
+        let base_dir = if base_dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            base_dir
+        };
+        let inputs = match &self.input {
+            BuildInput::One(input) => std::slice::from_ref(input),
+            BuildInput::Many(inputs) => inputs.as_slice(),
+        };
+        let mut args = vec![OsString::from("spreet")];
+        args.extend(inputs.iter().map(|input| input_arg(input, base_dir)));
+        args.push(base_dir.join(&self.output).into());
+        for (name, value) in &self.options {
+            // Like the input and output, files named in options are relative to the config file.
+            if let (true, Value::String(path)) = (PATH_OPTIONS.contains(&name.as_str()), value) {
+                let mut arg = OsString::from(format!("--{name}="));
+                arg.push(base_dir.join(path));
+                args.push(arg);
+                continue;
+            }
+            // Each value in a list is passed separately, as if the option had been repeated, so
+            // that values containing commas (such as `{a,b}` patterns) are kept whole.
+            let values = match value {
+                Value::Bool(false) => continue,
+                Value::Bool(true) => {
+                    args.push(format!("--{name}").into());
+                    continue;
+                }
+                Value::Array(values) => values.iter().map(option_value).collect(),
+                value => option_value(value).map(|value| vec![value]),
+            };
+            let Some(values) = values else {
+                return Err(format!("unsupported value for {name}"));
+            };
+            for value in values {
+                args.push(format!("--{name}={value}").into());
+            }
+        }
+        let cli = Cli::try_parse_from(args).map_err(|e| {
+            // Keep only the description of the error, without clap's usage message.
+            let message = e.to_string();
+            let message = message.lines().next().unwrap_or_default();
+            message.trim_start_matches("error: ").to_string()
+        })?;
+        Ok(cli.spritesheet)
+    }
+}
+
+/// Resolve an input directory from `base_dir`, keeping any `PREFIX=`. As on the command line, an
+/// input that's an existing directory as a whole is never split, even if it contains `=`.
+fn input_arg(input: &str, base_dir: &Path) -> OsString {
+    match input.split_once('=') {
+        Some((prefix, path)) if !prefix.is_empty() && !base_dir.join(input).is_dir() => {
+            let mut arg = OsString::from(format!("{prefix}="));
+            arg.push(base_dir.join(path));
+            arg
+        }
+        _ => base_dir.join(input).into(),
+    }
+}
+
+/// Format a number or string from a config file as a command-line argument value.
+fn option_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Number(number) => Some(number.to_string()),
+        Value::String(string) => Some(string.clone()),
+        _ => None,
+    }
+}
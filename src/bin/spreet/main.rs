@@ -1,98 +1,1098 @@
-use std::collections::BTreeMap;
-use std::num::NonZero;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
 
 use clap::Parser;
-use spreet::{get_svg_input_paths, load_svg, sprite_name, Optlevel, Sprite, Spritesheet};
+use rayon::prelude::*;
+use spreet::resvg::tiny_skia::Pixmap;
+use spreet::{
+    case_insensitive_name_collisions, check_sprite_names, get_image_input_paths_with_options,
+    get_svg_input_paths_with_options, is_raster_path, load_images, load_index, load_style,
+    near_duplicate_sprites, page_file_prefix, raster_pixel_ratio, ratio_file_prefix, sprite_name,
+    style_icon_references, validate_spritesheet, IconReference, InputImage, InputOptions,
+    MaxRectsPacker, RenderCache, RenderOptions, ShelfPacker, SkylinePacker, SpreetError,
+    SpreetResult, Sprite, Spritesheet,
+};
 
 mod cli;
+mod config;
+mod serve;
+mod watch;
 
 fn main() {
     let args = cli::Cli::parse();
+    let Some(jobs) = args.jobs else {
+        return run(&args);
+    };
+    // With `--jobs`, the command runs in a thread pool of its own rather than the global pool,
+    // whose size can't be changed once it's been started. The pool is also used by oxipng to
+    // optimise the PNG images.
+    match rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.get())
+        .build()
+    {
+        Ok(pool) => pool.install(|| run(&args)),
+        Err(e) => {
+            eprintln!("Error: could not start {jobs} threads ({e})");
+            std::process::exit(exitcode::OSERR);
+        }
+    }
+}
 
-    // The ratio between the pixels in an SVG image and the pixels in the resulting PNG sprite. A
-    // value of 2 means the PNGs will be double the size of the SVG images.
-    let pixel_ratio = if args.retina { 2 } else { args.ratio };
+/// Run the command given on the command line.
+fn run(args: &cli::Cli) {
+    match &args.command {
+        Some(cli::Command::Build(build_args)) => build_all(build_args),
+        Some(cli::Command::Extract(extract_args)) => extract(extract_args),
+        Some(cli::Command::Merge(merge_args)) => merge(merge_args),
+        Some(cli::Command::Diff(diff_args)) => diff(diff_args),
+        Some(cli::Command::Validate(validate_args)) => validate(validate_args),
+        Some(cli::Command::CheckStyle(check_style_args)) => check_style(check_style_args),
+        Some(cli::Command::Serve(serve_args)) => serve::serve(serve_args),
+        None if args.spritesheet.watch => watch::watch(&args.spritesheet, |_| {}),
+        None => build(&args.spritesheet, &mut ImageCache::default()),
+    }
+}
 
-    // Collect the file paths for all SVG images in the input directory.
-    // Read from all the input SVG files, convert them into bitmaps at the correct pixel ratio, and
-    // store them in a map. The keys are the SVG filenames without the `.svg` extension. The
-    // bitmapped SVGs will be added to the spritesheet, and the keys will be used as the unique
-    // sprite ids in the JSON index file.
-    let Ok(input_paths) = get_svg_input_paths(&args.input, args.recursive) else {
-        eprintln!("Error: no valid SVGs found in {:?}", args.input);
-        std::process::exit(exitcode::NOINPUT);
-    };
-    let sprites = input_paths
+/// Input images that have already been loaded, keyed by path, so that builds sharing inputs only
+/// parse each image once. Images that couldn't be loaded are kept with the reason why.
+#[derive(Default)]
+struct ImageCache(BTreeMap<PathBuf, Result<InputImage, String>>);
+
+impl ImageCache {
+    /// Load some images with [`load_images`], except for any that are already loaded, and return
+    /// each image or the reason why it isn't a valid image.
+    fn load(&mut self, paths: Vec<PathBuf>) -> BTreeMap<PathBuf, Result<InputImage, String>> {
+        let unloaded = paths
+            .iter()
+            .filter(|path| !self.0.contains_key(*path))
+            .collect::<Vec<_>>();
+        for (path, image) in load_images(&unloaded) {
+            self.0.insert(path, image.map_err(|e| e.to_string()));
+        }
+        paths
+            .into_iter()
+            .map(|path| {
+                let image = self.0[&path].clone();
+                (path, image)
+            })
+            .collect()
+    }
+
+    /// Forget an image, so that it's loaded again the next time it's used.
+    fn forget(&mut self, path: &Path) {
+        self.0.remove(path);
+    }
+}
+
+/// Create the spritesheets described by a config file.
+///
+/// Each build is run as if its options had been passed on the command line, and images used by
+/// more than one build are only parsed once.
+fn build_all(args: &cli::BuildArgs) {
+    let config = match config::Config::load(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: could not read config file {:?} ({e})", args.config);
+            std::process::exit(exitcode::CONFIG);
+        }
+    };
+    if config.builds.is_empty() {
+        eprintln!("Error: no builds found in {:?}", args.config);
+        std::process::exit(exitcode::CONFIG);
+    }
+
+    // Check every build before running any of them.
+    let base_dir = args.config.parent().unwrap_or(Path::new(""));
+    let builds = config
+        .builds
         .iter()
-        .map(|svg_path| {
-            if let Ok(tree) = load_svg(svg_path) {
-                let mut sprite = if args.sdf {
-                    Sprite::new_sdf(tree, pixel_ratio).expect("failed to load an SDF sprite")
-                } else {
-                    Sprite::new(tree, pixel_ratio).expect("failed to load a sprite")
-                };
-                if args.crop {
-                    sprite.crop(args.include_center);
-                }
-                if let Ok(name) = sprite_name(svg_path, args.input.as_path()) {
-                    (name, sprite)
-                } else {
-                    eprintln!("Error: cannot make a valid sprite name from {svg_path:?}");
-                    std::process::exit(exitcode::DATAERR);
-                }
-            } else {
-                eprintln!("{svg_path:?}: not a valid SVG image");
-                std::process::exit(exitcode::DATAERR);
+        .map(|(name, build)| match build.to_args(base_dir) {
+            Ok(build) => build,
+            Err(e) => {
+                eprintln!("Error: invalid build {name:?} in {:?} ({e})", args.config);
+                std::process::exit(exitcode::CONFIG);
             }
         })
-        .collect::<BTreeMap<String, Sprite>>();
+        .collect::<Vec<_>>();
+
+    let mut images = ImageCache::default();
+    for build_args in &builds {
+        build(build_args, &mut images);
+    }
+}
+
+/// An error that stops a build: a message for the user, and the exit code for the process.
+struct BuildError(String, exitcode::ExitCode);
+
+impl BuildError {
+    /// Print the error message and exit the process.
+    fn exit(self) -> ! {
+        eprintln!("{}", self.0);
+        std::process::exit(self.1);
+    }
+}
+
+/// A spritesheet saved by a build.
+#[derive(Clone)]
+struct SavedSpritesheet {
+    pixel_ratio: u8,
+    /// The path of the PNG image.
+    image: String,
+    /// The path of the index file, which is shared by all the pages with `--combined-index`.
+    index: String,
+    /// The spritesheet's page, if its index file is shared with other pages.
+    page: Option<usize>,
+}
+
+/// Create spritesheets from a directory of SVGs, exiting the process on error.
+fn build(args: &cli::SpritesheetArgs, images: &mut ImageCache) {
+    if let Err(e) = try_build(args, images, &mut BTreeMap::new()) {
+        e.exit();
+    }
+}
+
+/// Create spritesheets from a directory of SVGs.
+///
+/// Sprites are rendered from the images in `images`, and rendered sprites are stored in `sprites`,
+/// keyed by the path of their image. A sprite that's already in `sprites` isn't rendered again.
+///
+/// Returns the spritesheets that were saved.
+fn try_build(
+    args: &cli::SpritesheetArgs,
+    images: &mut ImageCache,
+    sprites: &mut BTreeMap<PathBuf, Sprite>,
+) -> Result<Vec<SavedSpritesheet>, BuildError> {
+    // Clap requires the input and output arguments when there's no subcommand.
+    let Some(output) = &args.output else {
+        unreachable!()
+    };
+    let inputs = describe_inputs(args);
+
+    // The ratios between the pixels in an SVG image and the pixels in the resulting PNG sprites. A
+    // value of 2 means the PNGs will be double the size of the SVG images. One spritesheet is
+    // output for each ratio.
+    let pixel_ratios = pixel_ratios(args);
+    // The sprites are first rendered at the first ratio, and then rendered again from the same
+    // parsed SVGs for any other ratios.
+    let pixel_ratio = pixel_ratios[0];
+
+    // Collect the file paths for all SVG images in the input directories.
+    // Read from all the input SVG files, convert them into bitmaps at the correct pixel ratio, and
+    // store them in a map. The keys are the SVG filenames without the `.svg` extension, and any
+    // prefix given for their input directory. The bitmapped SVGs will be added to the spritesheet,
+    // and the keys will be used as the unique sprite ids in the JSON index file.
+    // With `--raster`, PNG and WebP images are included too, and are resampled from the pixel ratio
+    // in their file names.
+    let input_images = resolve_name_conflicts(args, input_images(args)?)?;
+    // With `--style` or `--allowlist`, images that aren't used are skipped without being rendered.
+    let used_sprites = used_sprites(args)?;
+    let mut used_images = Vec::new();
+    // Images that haven't been rendered are loaded, and then rendered, in parallel.
+    let mut unrendered_paths = Vec::new();
+    for (name, svg_path) in input_images {
+        if let Some((_, used)) = &used_sprites {
+            if !used.iter().any(|reference| reference.matches(&name)) {
+                continue;
+            }
+        }
+        if !sprites.contains_key(&svg_path) {
+            unrendered_paths.push(svg_path.clone());
+        }
+        used_images.push((name, svg_path));
+    }
+    // With `--cache-dir`, SVG images that haven't changed since an earlier build are read from the
+    // cache without being parsed or rendered again.
+    let render_cache = args.cache_dir.as_ref().map(RenderCache::new);
+    let options = render_options(args, pixel_ratio);
+    let mut rendered_sprites = Vec::new();
+    if let Some(cache) = &render_cache {
+        let cached_paths;
+        (cached_paths, unrendered_paths) = unrendered_paths
+            .into_iter()
+            .partition(|path| !is_raster_path(path));
+        let cached_paths = cached_paths.into_iter().map(|path| (path, ())).collect();
+        rendered_sprites = render_sprites(cached_paths, |path, ()| {
+            cache.render(path, &options).map_err(|e| e.to_string())
+        });
+    }
+    let unrendered_images = images.load(unrendered_paths);
+    rendered_sprites.extend(render_sprites(unrendered_images, |path, image| {
+        render_sprite(args, image?, path, &options)
+    }));
+    // Images that can't be rendered are all reported together, with the reason why.
+    let mut invalid_images = Vec::new();
+    for (svg_path, sprite) in rendered_sprites {
+        match sprite {
+            Ok(sprite) => {
+                sprites.insert(svg_path, sprite);
+            }
+            Err(e) => invalid_images.push(format!("{svg_path:?}: {e}")),
+        }
+    }
+    let named_sprites = used_images
+        .into_iter()
+        .filter_map(|(name, svg_path)| Some((name, sprites.get(&svg_path)?.clone())))
+        .collect::<BTreeMap<_, _>>();
+
+    if !invalid_images.is_empty() {
+        let count = invalid_images.len();
+        let list = invalid_images.join("\n  ");
+        if !args.skip_invalid {
+            return Err(BuildError(
+                format!(
+                    "Error: could not load {count} image(s) (pass --skip-invalid to leave them \
+                     out):\n  {list}"
+                ),
+                exitcode::DATAERR,
+            ));
+        }
+        eprintln!("Warning: left out {count} image(s) that could not be loaded:\n  {list}");
+    }
+
+    if let Some((file, used)) = &used_sprites {
+        let missing = used
+            .iter()
+            .filter_map(|reference| match reference {
+                IconReference::Name(name) if !named_sprites.contains_key(name) => Some(name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if args.strict && !missing.is_empty() {
+            let missing = missing
+                .iter()
+                .map(|name| format!("{name:?}"))
+                .collect::<Vec<_>>();
+            return Err(BuildError(
+                format!(
+                    "Error: {file:?} uses images that aren't in {inputs}: {}",
+                    missing.join(", ")
+                ),
+                exitcode::DATAERR,
+            ));
+        }
+        for name in missing {
+            eprintln!("Warning: {file:?} uses {name:?}, which isn't in {inputs}");
+        }
+    }
+
+    if named_sprites.is_empty() {
+        return Err(BuildError(
+            format!("Error: no valid SVGs found in {inputs}"),
+            exitcode::NOINPUT,
+        ));
+    }
 
-    if sprites.is_empty() {
-        eprintln!("Error: no valid SVGs found in {:?}", args.input);
-        std::process::exit(exitcode::NOINPUT);
+    if let Some(max_difference) = args.near_duplicates {
+        report_near_duplicates(&named_sprites, max_difference);
     }
 
     let mut spritesheet_builder = Spritesheet::build();
-    spritesheet_builder.sprites(sprites);
+    spritesheet_builder.sprites(named_sprites);
     spritesheet_builder.spacing(args.spacing);
+    if let Some(cache) = &render_cache {
+        spritesheet_builder.render_cache(cache.clone());
+    }
     if args.unique {
         spritesheet_builder.make_unique();
     };
     if args.sdf {
         spritesheet_builder.make_sdf();
     };
+    if let Some(max_size) = args.max_size {
+        spritesheet_builder.max_size(max_size);
+    }
+    match args.packer {
+        None | Some(cli::PackerKind::Crunch) => {}
+        Some(cli::PackerKind::Shelf) => {
+            spritesheet_builder.packer(ShelfPacker);
+        }
+        Some(cli::PackerKind::Skyline) => {
+            spritesheet_builder.packer(SkylinePacker);
+        }
+        Some(cli::PackerKind::MaxRects) => {
+            spritesheet_builder.packer(MaxRectsPacker);
+        }
+    }
+    if args.any_size {
+        spritesheet_builder.allow_any_size();
+    }
+    if args.square {
+        spritesheet_builder.make_square();
+    }
+    if let Some(width) = args.width {
+        spritesheet_builder.fixed_width(width);
+    }
 
-    // Generate sprite sheet
-    let Some(spritesheet) = spritesheet_builder.generate() else {
-        eprintln!("Error: could not pack the sprites within an area fifty times their size.");
-        std::process::exit(exitcode::DATAERR);
+    // With `--ratios`, each spritesheet's file name gets a suffix for its pixel ratio.
+    let file_prefix = |ratio| {
+        if args.ratios.is_some() {
+            ratio_file_prefix(output, ratio)
+        } else {
+            output.clone()
+        }
     };
 
-    let optlevel = match (args.oxipng, args.zopfli) {
-        (None, None) => Optlevel::default(),
-        (Some(level), None) => Optlevel::Oxipng { level },
-        (None, Some(iterations)) => Optlevel::Zopfli {
-            iterations: NonZero::new(iterations).unwrap(),
-        },
-        (Some(_), Some(_)) => unreachable!(),
+    // Read the index files from the previous build, if there are any, so that sprites can be kept
+    // in the same position.
+    if args.incremental {
+        for &ratio in &pixel_ratios {
+            let index_path = format!("{}.json", file_prefix(ratio));
+            if !Path::new(&index_path).exists() {
+                continue;
+            }
+            match load_index(&index_path) {
+                Ok(mut index) => {
+                    // Simple index files don't include the pixel ratio.
+                    for description in index.values_mut() {
+                        description.pixel_ratio = ratio;
+                    }
+                    spritesheet_builder.previous_index(index);
+                }
+                Err(e) => {
+                    return Err(BuildError(
+                        format!("Error: could not read previous sprite index {index_path} ({e})"),
+                        exitcode::DATAERR,
+                    ));
+                }
+            }
+        }
+    }
+
+    // Generate a sprite sheet for each pixel ratio, split into pages with `--max-size`.
+    let spritesheets = match (
+        spritesheet_builder.generate_ratio_pages(&pixel_ratios),
+        args.max_size,
+    ) {
+        (Ok(spritesheets), _) => spritesheets,
+        (Err(SpreetError::PackingError { .. }), Some(max_size)) => {
+            return Err(BuildError(
+                format!(
+                    "Error: could not pack the sprites into pages of {max_size}x{max_size} pixels."
+                ),
+                exitcode::DATAERR,
+            ));
+        }
+        (Err(e), _) => {
+            return Err(BuildError(
+                format!("Error: could not create the spritesheet ({e})"),
+                exitcode::DATAERR,
+            ));
+        }
     };
 
-    // Save the bitmapped spritesheet to a local PNG.
-    let file_prefix = args.output;
-    let spritesheet_path = format!("{file_prefix}.png");
-    if let Err(e) = spritesheet.save_spritesheet_at(&spritesheet_path, optlevel) {
-        eprintln!("Error: could not save spritesheet to {spritesheet_path} ({e})");
-        std::process::exit(exitcode::IOERR);
+    // Sprites that couldn't be saved in the cache are still used, and are rendered again next time.
+    if let Some(cache) = &render_cache {
+        let store_errors = cache.take_store_errors();
+        if let Some(e) = store_errors.first() {
+            eprintln!(
+                "Warning: could not save {} rendered images in cache directory {:?} ({e})",
+                store_errors.len(),
+                cache.dir()
+            );
+        }
+    }
+
+    let mut saved = Vec::new();
+    for (ratio, pages) in spritesheets {
+        if args.max_size.is_none() {
+            for spritesheet in &pages {
+                save_spritesheet(spritesheet, &file_prefix(ratio), &args.output_options)?;
+                if args.packer.is_some() {
+                    report_fill_ratio(spritesheet, &file_prefix(ratio));
+                }
+                saved.push(SavedSpritesheet {
+                    pixel_ratio: ratio,
+                    image: format!("{}.png", file_prefix(ratio)),
+                    index: format!("{}.json", file_prefix(ratio)),
+                    page: None,
+                });
+            }
+            continue;
+        }
+        // Each page is named with a page suffix before any pixel ratio suffix, e.g. `sprite-0@2x`.
+        for (page, spritesheet) in pages.iter().enumerate() {
+            let page_prefix = if args.ratios.is_some() {
+                ratio_file_prefix(&page_file_prefix(output, page), ratio)
+            } else {
+                page_file_prefix(output, page)
+            };
+            if args.combined_index {
+                save_spritesheet_image(spritesheet, &page_prefix, &args.output_options)?;
+            } else {
+                save_spritesheet(spritesheet, &page_prefix, &args.output_options)?;
+            }
+            if args.packer.is_some() {
+                report_fill_ratio(spritesheet, &page_prefix);
+            }
+            saved.push(SavedSpritesheet {
+                pixel_ratio: ratio,
+                image: format!("{page_prefix}.png"),
+                index: if args.combined_index {
+                    format!("{}.json", file_prefix(ratio))
+                } else {
+                    format!("{page_prefix}.json")
+                },
+                page: args.combined_index.then_some(page),
+            });
+        }
+        if args.combined_index {
+            let file_prefix = file_prefix(ratio);
+            let minify = args.output_options.minify_index_file;
+            if let Err(e) = Spritesheet::save_combined_index(&pages, &file_prefix, minify) {
+                return Err(BuildError(
+                    format!("Error: could not save sprite index to {file_prefix} ({e})"),
+                    exitcode::IOERR,
+                ));
+            }
+        }
+    }
+    Ok(saved)
+}
+
+/// The pixel ratios selected by `--ratio`, `--retina` or `--ratios`.
+fn pixel_ratios(args: &cli::SpritesheetArgs) -> Vec<u8> {
+    match &args.ratios {
+        Some(ratios) => ratios.clone(),
+        None if args.retina => vec![2],
+        None => vec![args.ratio],
+    }
+}
+
+/// The sprites to include with `--style` or `--allowlist`, and the file they were read from.
+///
+/// Returns `None` if all the sprites should be included.
+fn used_sprites(
+    args: &cli::SpritesheetArgs,
+) -> Result<Option<(&PathBuf, Vec<IconReference>)>, BuildError> {
+    if let Some(style_path) = &args.style {
+        let style = load_style(style_path).map_err(|e| {
+            BuildError(
+                format!("Error: could not read style {style_path:?} ({e})"),
+                exitcode::DATAERR,
+            )
+        })?;
+        let used = style_icon_references(&style).into_keys().collect();
+        Ok(Some((style_path, used)))
+    } else if let Some(allowlist_path) = &args.allowlist {
+        let allowlist = std::fs::read_to_string(allowlist_path).map_err(|e| {
+            BuildError(
+                format!("Error: could not read allowlist {allowlist_path:?} ({e})"),
+                exitcode::NOINPUT,
+            )
+        })?;
+        // Blank lines and comments starting with `#` are ignored.
+        let used = allowlist
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|name| IconReference::Name(name.to_string()))
+            .collect();
+        Ok(Some((allowlist_path, used)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The images in the input directories, including PNG and WebP images with `--raster`, and
+/// filtered by `--include`, `--exclude` and any `.spreetignore` files. Each image is returned with
+/// the name of its sprite, which starts with `PREFIX/` if its directory was given as
+/// `PREFIX=INPUT`.
+///
+/// With `--raster`, the spritesheets saved by the build are left out, in case they're saved in an
+/// input directory.
+fn input_images(args: &cli::SpritesheetArgs) -> Result<Vec<(String, PathBuf)>, BuildError> {
+    let options = InputOptions {
+        recursive: args.recursive,
+        include: args.include.clone(),
+        exclude: args.exclude.clone(),
     };
+    let mut images = collect_input_images(&args.input, &options, args.raster)?;
+    // Otherwise the next build would include the spritesheet as a sprite, and `--watch` would
+    // rebuild the spritesheet every time it's saved.
+    if let (true, Some(output)) = (args.raster, &args.output) {
+        images.retain(|(_, path)| !is_output_image(path, output));
+    }
+    Ok(images)
+}
+
+/// Whether `path` is a spritesheet image saved to `output`, such as `sprite.png`, `sprite@2x.png`,
+/// `sprite-0.png` or `sprite-0@2x.png` for an output of `sprite`.
+fn is_output_image(path: &Path, output: &str) -> bool {
+    let output = Path::new(output);
+    let (Some(stem), Some(output_name)) = (
+        path.file_stem().and_then(|stem| stem.to_str()),
+        output.file_name().and_then(|name| name.to_str()),
+    ) else {
+        return false;
+    };
+    let Some(suffix) = stem.strip_prefix(output_name) else {
+        return false;
+    };
+    let (page, ratio) = suffix.split_at(suffix.find('@').unwrap_or(suffix.len()));
+    let is_page = page.is_empty()
+        || page
+            .strip_prefix('-')
+            .is_some_and(|page| page.parse::<usize>().is_ok());
+    let is_ratio = ratio.is_empty()
+        || ratio
+            .strip_prefix('@')
+            .and_then(|ratio| ratio.strip_suffix('x'))
+            .is_some_and(|ratio| ratio.parse::<u8>().is_ok());
+    if path
+        .extension()
+        .map_or(true, |extension| extension != "png")
+        || !is_page
+        || !is_ratio
+    {
+        return false;
+    }
+    // The files are only the same if they're in the same directory.
+    let dir = |path: &Path| {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        std::fs::canonicalize(dir.unwrap_or(Path::new("."))).ok()
+    };
+    dir(path).is_some() && dir(path) == dir(output)
+}
+
+/// The images in `inputs` that aren't left out by `options`, including PNG and WebP images if
+/// `raster` is true, with the names of their sprites. See [`input_images`].
+fn collect_input_images(
+    inputs: &[cli::PrefixedPath],
+    options: &InputOptions,
+    raster: bool,
+) -> Result<Vec<(String, PathBuf)>, BuildError> {
+    let mut images = Vec::new();
+    for cli::PrefixedPath {
+        prefix,
+        path: input,
+    } in inputs
+    {
+        let paths = if raster {
+            get_image_input_paths_with_options(input, options)
+        } else {
+            get_svg_input_paths_with_options(input, options)
+        };
+        let mut paths = paths.map_err(|e| match e {
+            SpreetError::PatternError(e) => BuildError(
+                format!("Error: could not use the input patterns for {input:?} ({e})"),
+                exitcode::DATAERR,
+            ),
+            _ => BuildError(
+                format!("Error: no valid SVGs found in {input:?}"),
+                exitcode::NOINPUT,
+            ),
+        })?;
+        // Sort the paths so that the first of several images with the same name is predictable.
+        paths.sort();
+        for path in paths {
+            let Ok(name) = sprite_name(&path, input) else {
+                return Err(BuildError(
+                    format!("Error: cannot make a valid sprite name from {path:?}"),
+                    exitcode::DATAERR,
+                ));
+            };
+            let name = match prefix {
+                Some(prefix) => format!("{prefix}/{name}"),
+                None => name,
+            };
+            images.push((name, path));
+        }
+    }
+    Ok(images)
+}
+
+/// Apply `--on-conflict` to input images that have the same sprite name, and warn about sprite
+/// names that differ only by case.
+fn resolve_name_conflicts(
+    args: &cli::SpritesheetArgs,
+    images: Vec<(String, PathBuf)>,
+) -> Result<Vec<(String, PathBuf)>, BuildError> {
+    for collision in case_insensitive_name_collisions(&images) {
+        eprintln!(
+            "Warning: sprite names that differ only by case can't be checked out together on \
+             case-insensitive file systems: {collision}"
+        );
+    }
+    let collisions = match check_sprite_names(&images) {
+        Ok(()) => return Ok(images),
+        Err(SpreetError::NameCollisionError(collisions)) => collisions,
+        Err(_) => unreachable!(),
+    };
+    match args.on_conflict {
+        cli::NameConflictPolicy::Error => {
+            let collisions = collisions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            return Err(BuildError(
+                format!(
+                    "Error: more than one image has the same sprite name: {}",
+                    collisions.join(", ")
+                ),
+                exitcode::DATAERR,
+            ));
+        }
+        cli::NameConflictPolicy::Warn => {
+            for collision in &collisions {
+                eprintln!(
+                    "Warning: more than one image has the same sprite name, keeping the first: \
+                     {collision}"
+                );
+            }
+        }
+        cli::NameConflictPolicy::First => {}
+    }
+    let mut names = BTreeSet::new();
+    Ok(images
+        .into_iter()
+        .filter(|(name, _)| names.insert(name.clone()))
+        .collect())
+}
+
+/// The input directories, formatted for messages, e.g. `"icons"` or `"maki", "temaki"`.
+fn describe_inputs(args: &cli::SpritesheetArgs) -> String {
+    let paths = args
+        .input
+        .iter()
+        .map(|input| format!("{:?}", input.path))
+        .collect::<Vec<_>>();
+    paths.join(", ")
+}
+
+/// Render the sprites for some loaded input images with `render`, in parallel. The results are
+/// returned in the same order as the images.
+fn render_sprites<T: Send>(
+    images: BTreeMap<PathBuf, T>,
+    render: impl Fn(&Path, T) -> Result<Sprite, String> + Send + Sync,
+) -> Vec<(PathBuf, Result<Sprite, String>)> {
+    images
+        .into_par_iter()
+        .map(|(path, image)| {
+            let sprite = render(&path, image);
+            (path, sprite)
+        })
+        .collect()
+}
+
+/// The options for rendering SVG images at `pixel_ratio`, from `--sdf`, `--crop` and
+/// `--include-center`.
+fn render_options(args: &cli::SpritesheetArgs, pixel_ratio: u8) -> RenderOptions {
+    RenderOptions {
+        pixel_ratio,
+        sdf: args.sdf.then(|| args.sdf_options()),
+        crop: args.crop,
+        include_center: args.include_center,
+    }
+}
+
+/// Render the sprite for an input image with `options`, or return the reason why it can't be
+/// rendered.
+fn render_sprite(
+    args: &cli::SpritesheetArgs,
+    image: InputImage,
+    path: &Path,
+    options: &RenderOptions,
+) -> Result<Sprite, String> {
+    match image {
+        InputImage::Raster(pixmap) => {
+            let source_ratio = raster_pixel_ratio(path);
+            let sprite = if args.sdf {
+                Sprite::from_pixmap_sdf_with_options(
+                    pixmap,
+                    source_ratio,
+                    options.pixel_ratio,
+                    args.sdf_options(),
+                )
+            } else {
+                Sprite::from_pixmap(pixmap, source_ratio, options.pixel_ratio)
+            };
+            // Images that are empty, or too large for a bitmap, can't be made into a sprite.
+            let mut sprite = sprite.map_err(|e| e.to_string())?;
+            if args.crop {
+                sprite.crop(args.include_center);
+            }
+            Ok(sprite)
+        }
+        InputImage::Svg(tree) => options.render(*tree).map_err(|e| e.to_string()),
+    }
+}
+
+/// Print the pairs of sprites that are almost, but not exactly, the same.
+fn report_near_duplicates(sprites: &BTreeMap<String, Sprite>, max_difference: f32) {
+    for near_duplicate in near_duplicate_sprites(sprites, max_difference) {
+        println!(
+            "near duplicates: {} and {} ({} pixels differ, {:.2}% different)",
+            near_duplicate.first,
+            near_duplicate.second,
+            near_duplicate.differing_pixels,
+            near_duplicate.difference * 100.0
+        );
+    }
+}
+
+/// Print the size of a spritesheet and how much of it is filled by sprites.
+fn report_fill_ratio(spritesheet: &Spritesheet, file_prefix: &str) {
+    let pixmap = spritesheet.pixmap();
+    println!(
+        "{file_prefix}.png: {}x{} pixels, {:.1}% filled",
+        pixmap.width(),
+        pixmap.height(),
+        spritesheet.fill_ratio() * 100.0
+    );
+}
+
+/// Save a spritesheet and its index file.
+fn save_spritesheet(
+    spritesheet: &Spritesheet,
+    file_prefix: &str,
+    args: &cli::OutputArgs,
+) -> Result<(), BuildError> {
+    save_spritesheet_image(spritesheet, file_prefix, args)?;
 
     // Save the index file to a local JSON file with the same name as the spritesheet.
     let res = if args.simple_index_file {
-        spritesheet.save_index_simple(&file_prefix, args.minify_index_file)
+        spritesheet.save_index_simple(file_prefix, args.minify_index_file)
+    } else {
+        spritesheet.save_index(file_prefix, args.minify_index_file)
+    };
+    res.map_err(|e| {
+        BuildError(
+            format!("Error: could not save sprite index to {file_prefix} ({e})"),
+            exitcode::IOERR,
+        )
+    })
+}
+
+/// Save a spritesheet's PNG image and any reports selected with `--report` (but not its index
+/// file).
+fn save_spritesheet_image(
+    spritesheet: &Spritesheet,
+    file_prefix: &str,
+    args: &cli::OutputArgs,
+) -> Result<(), BuildError> {
+    // Save the bitmapped spritesheet to a local PNG.
+    let spritesheet_path = format!("{file_prefix}.png");
+    spritesheet
+        .save_spritesheet_at(&spritesheet_path, args.optlevel())
+        .map_err(|e| {
+            BuildError(
+                format!("Error: could not save spritesheet to {spritesheet_path} ({e})"),
+                exitcode::IOERR,
+            )
+        })?;
+
+    for format in &args.report {
+        let (res, extension) = match format {
+            cli::ReportFormat::Html => (spritesheet.save_html_report(file_prefix), "html"),
+            cli::ReportFormat::Markdown => (spritesheet.save_markdown_report(file_prefix), "md"),
+        };
+        res.map_err(|e| {
+            BuildError(
+                format!("Error: could not save sprite report to {file_prefix}.{extension} ({e})"),
+                exitcode::IOERR,
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Extract the sprites from a spritesheet into individual PNG images.
+///
+/// Each sprite is saved to a file named after the sprite, with a `@2x`-style suffix if its pixel
+/// ratio isn't 1. Sprite names that contain `/` are saved in sub-directories, and sprites that
+/// share an image under several names (see `--unique`) are saved once for each name.
+fn extract(args: &cli::ExtractArgs) {
+    let index_path = args
+        .index
+        .clone()
+        .unwrap_or_else(|| args.spritesheet.with_extension("json"));
+    let spritesheet = match Spritesheet::load(&args.spritesheet, &index_path) {
+        Ok(spritesheet) => spritesheet,
+        Err(e) => {
+            eprintln!(
+                "Error: could not load spritesheet {:?} with index {index_path:?} ({e})",
+                args.spritesheet
+            );
+            std::process::exit(exitcode::DATAERR);
+        }
+    };
+
+    for (name, description) in spritesheet.get_index() {
+        // Don't let a sprite name write outside the output directory.
+        if !Path::new(name)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            eprintln!("Error: cannot make a valid file name from sprite {name:?}");
+            std::process::exit(exitcode::DATAERR);
+        }
+        let Some(pixmap) = spritesheet.sprite_pixmap(name) else {
+            eprintln!("Error: sprite {name:?} is empty");
+            std::process::exit(exitcode::DATAERR);
+        };
+        let sprite_path = args.output.join(format!(
+            "{}.png",
+            ratio_file_prefix(name, description.pixel_ratio)
+        ));
+        if let Err(e) = save_sprite(&pixmap, &sprite_path) {
+            eprintln!("Error: could not save sprite to {sprite_path:?} ({e})");
+            std::process::exit(exitcode::IOERR);
+        }
+    }
+}
+
+/// Merge several spritesheets into one.
+///
+/// The spritesheets are sliced into their sprites, which are then packed into a new spritesheet.
+/// Sprite names from a spritesheet given as `PREFIX=SPRITESHEET` are prefixed with `PREFIX/`.
+fn merge(args: &cli::MergeArgs) {
+    let mut sprites = BTreeMap::new();
+    for cli::PrefixedPath { prefix, path } in &args.spritesheets {
+        let index_path = path.with_extension("json");
+        let spritesheet_sprites = match Spritesheet::load(path, &index_path) {
+            Ok(spritesheet) => spritesheet.sprites(),
+            Err(e) => {
+                eprintln!(
+                    "Error: could not load spritesheet {path:?} with index {index_path:?} ({e})"
+                );
+                std::process::exit(exitcode::DATAERR);
+            }
+        };
+        let spritesheet_sprites = match spritesheet_sprites {
+            Ok(spritesheet_sprites) => spritesheet_sprites,
+            Err(e) => {
+                eprintln!("Error: could not slice spritesheet {path:?} into sprites ({e})");
+                std::process::exit(exitcode::DATAERR);
+            }
+        };
+        for (name, sprite) in spritesheet_sprites {
+            let name = match prefix {
+                Some(prefix) => format!("{prefix}/{name}"),
+                None => name,
+            };
+            match sprites.entry(name) {
+                Entry::Vacant(entry) => {
+                    entry.insert(sprite);
+                }
+                Entry::Occupied(mut entry) => match args.on_conflict {
+                    cli::ConflictPolicy::Error => {
+                        eprintln!(
+                            "Error: more than one spritesheet has a sprite named {:?}",
+                            entry.key()
+                        );
+                        std::process::exit(exitcode::DATAERR);
+                    }
+                    cli::ConflictPolicy::First => {}
+                    cli::ConflictPolicy::Last => {
+                        entry.insert(sprite);
+                    }
+                },
+            }
+        }
+    }
+
+    // Sprites from spritesheets with different pixel ratios can only be merged by resampling them
+    // to the same ratio.
+    if let Some(ratio) = args.ratio {
+        for sprite in sprites.values_mut() {
+            match sprite.with_pixel_ratio(ratio) {
+                Ok(resampled) => *sprite = resampled,
+                Err(e) => {
+                    eprintln!(
+                        "Error: could not resample the sprites to a pixel ratio of {ratio} ({e})"
+                    );
+                    std::process::exit(exitcode::DATAERR);
+                }
+            }
+        }
     } else {
-        spritesheet.save_index(&file_prefix, args.minify_index_file)
+        let mut ratios = sprites.values().map(Sprite::pixel_ratio);
+        if let Some(first_ratio) = ratios.next() {
+            if ratios.any(|ratio| ratio != first_ratio) {
+                eprintln!(
+                    "Error: the spritesheets have different pixel ratios (use --ratio to resample)"
+                );
+                std::process::exit(exitcode::DATAERR);
+            }
+        }
+    }
+
+    let mut spritesheet_builder = Spritesheet::build();
+    spritesheet_builder.sprites(sprites);
+    spritesheet_builder.spacing(args.spacing);
+    if args.unique {
+        spritesheet_builder.make_unique();
     };
-    if let Err(e) = res {
-        eprintln!("Error: could not save sprite index to {file_prefix} ({e})");
-        std::process::exit(exitcode::IOERR);
+    let spritesheet = match spritesheet_builder.generate() {
+        Ok(spritesheet) => spritesheet,
+        Err(e) => {
+            eprintln!("Error: could not create the spritesheet ({e})");
+            std::process::exit(exitcode::DATAERR);
+        }
     };
+    if let Err(e) = save_spritesheet(&spritesheet, &args.output, &args.output_options) {
+        e.exit();
+    }
+}
+
+/// Compare two spritesheets, printing the sprites that differ.
+///
+/// Exits with status 1 if any sprites have been added, removed, renamed, resized or changed, so
+/// that a CI job can fail when a spritesheet isn't what it should be.
+fn diff(args: &cli::DiffArgs) {
+    let load = |path: &PathBuf| {
+        let index_path = path.with_extension("json");
+        Spritesheet::load(path, &index_path).unwrap_or_else(|e| {
+            eprintln!("Error: could not load spritesheet {path:?} with index {index_path:?} ({e})");
+            std::process::exit(exitcode::DATAERR);
+        })
+    };
+    let (old, new) = (load(&args.old), load(&args.new));
+    let mut diff = old.diff(&new);
+    diff.changed
+        .retain(|changed| changed.difference > args.threshold);
+
+    for name in &diff.added {
+        println!("added: {name}");
+    }
+    for name in &diff.removed {
+        println!("removed: {name}");
+    }
+    for (old_name, new_name) in &diff.renamed {
+        println!("renamed: {old_name} -> {new_name}");
+    }
+    for resized in &diff.resized {
+        let ((old_width, old_height), (new_width, new_height)) =
+            (resized.old_size, resized.new_size);
+        println!(
+            "resized: {} ({old_width}x{old_height} -> {new_width}x{new_height})",
+            resized.name
+        );
+    }
+    for changed in &diff.changed {
+        println!(
+            "changed: {} ({:.1}% different)",
+            changed.name,
+            changed.difference * 100.0
+        );
+    }
+
+    if let Some(path) = &args.diff_image {
+        // With no sprites to show, there's no image to save.
+        if let Some(image) = diff.image(&old, &new) {
+            if let Err(e) = save_sprite(&image, path) {
+                eprintln!("Error: could not save diff image to {path:?} ({e})");
+                std::process::exit(exitcode::IOERR);
+            }
+        }
+    }
+    if !diff.is_empty() {
+        // Like `diff`, exit with status 1 when there are differences.
+        std::process::exit(1);
+    }
+}
+
+/// Check a spritesheet's index file for problems.
+///
+/// The problems are printed as a JSON array, with the name of the sprite, the kind of problem and a
+/// description of it for each problem. Exits with an error if there are any problems.
+fn validate(args: &cli::ValidateArgs) {
+    let index_path = args
+        .index
+        .clone()
+        .unwrap_or_else(|| args.spritesheet.with_extension("json"));
+    let problems = match validate_spritesheet(&args.spritesheet, &index_path) {
+        Ok(problems) => problems,
+        Err(e) => {
+            eprintln!(
+                "Error: could not load spritesheet {:?} with index {index_path:?} ({e})",
+                args.spritesheet
+            );
+            std::process::exit(exitcode::DATAERR);
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&problems).unwrap());
+    if !problems.is_empty() {
+        std::process::exit(exitcode::DATAERR);
+    }
+}
+
+/// Compare the icons used by a style with the sprites that are available, either from an input
+/// directory or an existing index file.
+///
+/// Prints icons that the style uses but that aren't available (`missing`), sprites that the style
+/// doesn't use (`unused`), and icon names that are only known when the map is displayed
+/// (`runtime`), with the sprites they could refer to. Exits with an error if any icons are missing.
+fn check_style(args: &cli::CheckStyleArgs) {
+    let style = match load_style(&args.style) {
+        Ok(style) => style,
+        Err(e) => {
+            eprintln!("Error: could not read style {:?} ({e})", args.style);
+            std::process::exit(exitcode::DATAERR);
+        }
+    };
+    let sprite_names: BTreeSet<String> = match &args.index {
+        // The sprite names are collected in the same way as for a build, so that they're the same
+        // as the names in the spritesheet.
+        None => {
+            let options = InputOptions {
+                recursive: args.recursive,
+                include: args.include.clone(),
+                exclude: args.exclude.clone(),
+            };
+            match collect_input_images(&args.input, &options, args.raster) {
+                Ok(images) => images.into_iter().map(|(name, _)| name).collect(),
+                Err(e) => e.exit(),
+            }
+        }
+        Some(index) => match load_index(index) {
+            Ok(index) => index.into_keys().collect(),
+            Err(e) => {
+                eprintln!("Error: could not read sprite index {index:?} ({e})");
+                std::process::exit(exitcode::DATAERR);
+            }
+        },
+    };
+
+    let references = style_icon_references(&style);
+    let layers = |ids: &BTreeSet<String>| ids.iter().cloned().collect::<Vec<_>>().join(", ");
+    let mut missing = false;
+    for (reference, ids) in &references {
+        if let IconReference::Name(name) = reference {
+            if !sprite_names.contains(name) {
+                println!("missing: {name} (used by {})", layers(ids));
+                missing = true;
+            }
+        }
+    }
+    for name in &sprite_names {
+        if !references.keys().any(|reference| reference.matches(name)) {
+            println!("unused: {name}");
+        }
+    }
+    for (reference, ids) in &references {
+        if let IconReference::Runtime(template) = reference {
+            let matches = sprite_names
+                .iter()
+                .filter(|name| reference.matches(name))
+                .count();
+            let plural = if matches == 1 { "" } else { "s" };
+            println!(
+                "runtime: {template} (used by {}, matches {matches} sprite{plural})",
+                layers(ids)
+            );
+        }
+    }
+    if missing {
+        std::process::exit(exitcode::DATAERR);
+    }
+}
+
+/// Save a sprite's bitmap to a PNG image, creating its parent directories if needed.
+fn save_sprite(pixmap: &Pixmap, path: &Path) -> SpreetResult<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(std::fs::write(path, pixmap.encode_png()?)?)
 }
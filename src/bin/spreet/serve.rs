@@ -0,0 +1,155 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::Serialize;
+
+use crate::cli::ServeArgs;
+use crate::watch::watch;
+use crate::SavedSpritesheet;
+
+/// The preview page, which shows every sprite in the spritesheets and reloads them when they're
+/// saved again. It doesn't load anything from the internet, so it works offline.
+const PREVIEW_PAGE: &str = include_str!("preview.html");
+
+/// The spritesheets being previewed, as served to the preview page.
+#[derive(Default, Serialize)]
+struct Preview {
+    /// Incremented every time the spritesheets are saved, so that the page knows when to reload.
+    version: u64,
+    spritesheets: Vec<PreviewSpritesheet>,
+    /// The paths of the files that can be downloaded, by their position in the list.
+    #[serde(skip)]
+    files: Vec<PathBuf>,
+}
+
+/// A spritesheet being previewed, with the URLs of its image and index file.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PreviewSpritesheet {
+    pixel_ratio: u8,
+    image: String,
+    index: String,
+    page: Option<usize>,
+}
+
+impl Preview {
+    /// Replace the spritesheets being previewed with newly saved ones.
+    fn update(&mut self, saved: Vec<SavedSpritesheet>) {
+        self.version += 1;
+        self.files.clear();
+        self.spritesheets = saved
+            .into_iter()
+            .map(|spritesheet| PreviewSpritesheet {
+                pixel_ratio: spritesheet.pixel_ratio,
+                image: self.add_file(spritesheet.image),
+                index: self.add_file(spritesheet.index),
+                page: spritesheet.page,
+            })
+            .collect();
+    }
+
+    /// Allow a file to be downloaded, returning its URL relative to the preview page.
+    fn add_file(&mut self, path: String) -> String {
+        let path = PathBuf::from(path);
+        let id = match self.files.iter().position(|file| *file == path) {
+            Some(id) => id,
+            None => {
+                self.files.push(path);
+                self.files.len() - 1
+            }
+        };
+        format!("files/{id}")
+    }
+}
+
+/// Build the spritesheet and serve a preview page for it on localhost, rebuilding the spritesheet
+/// and reloading the page whenever an image in the input directories changes. Runs until the
+/// process is stopped.
+pub fn serve(args: &ServeArgs) -> ! {
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, args.port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!(
+                "Error: could not serve a preview on port {} ({e})",
+                args.port
+            );
+            std::process::exit(exitcode::UNAVAILABLE);
+        }
+    };
+    // With `--port 0`, the operating system chooses a free port.
+    let port = listener.local_addr().map_or(args.port, |addr| addr.port());
+
+    let preview = Arc::new(Mutex::new(Preview::default()));
+    let server_preview = Arc::clone(&preview);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let preview = Arc::clone(&server_preview);
+            // A failed response only affects the browser that requested it.
+            thread::spawn(move || respond(stream, &preview).ok());
+        }
+    });
+    println!("Previewing the spritesheet at http://localhost:{port}/");
+
+    watch(&args.spritesheet, |saved| {
+        preview.lock().unwrap().update(saved);
+    })
+}
+
+/// Read an HTTP request from `stream` and send the response.
+fn respond(stream: TcpStream, preview: &Mutex<Preview>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers aren't needed, but have to be read before responding.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+
+    let mut request = request_line.split_whitespace();
+    let (method, target) = (
+        request.next().unwrap_or_default(),
+        request.next().unwrap_or_default(),
+    );
+    let path = target.split('?').next().unwrap_or_default();
+    let response = match (method, path) {
+        ("GET", "/") => Some(("text/html; charset=utf-8", PREVIEW_PAGE.as_bytes().to_vec())),
+        ("GET", "/preview.json") => Some((
+            "application/json",
+            serde_json::to_vec(&*preview.lock().unwrap())?,
+        )),
+        ("GET", path) => path
+            .strip_prefix("/files/")
+            .and_then(|id| id.parse::<usize>().ok())
+            .and_then(|id| preview.lock().unwrap().files.get(id).cloned())
+            .and_then(|file| {
+                let content_type = match file.extension() {
+                    Some(ext) if ext == "png" => "image/png",
+                    _ => "application/json",
+                };
+                Some((content_type, std::fs::read(file).ok()?))
+            }),
+        _ => None,
+    };
+
+    let (status, content_type, body) = match response {
+        Some((content_type, body)) => ("200 OK", content_type, body),
+        None if method == "GET" => ("404 Not Found", "text/plain", b"Not found".to_vec()),
+        None => (
+            "405 Method Not Allowed",
+            "text/plain",
+            b"Method not allowed".to_vec(),
+        ),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::cli::SpritesheetArgs;
+use crate::{describe_inputs, input_images, try_build, ImageCache, SavedSpritesheet};
+
+/// How long to wait for more changes after an image changes, before rebuilding the spritesheet.
+/// Editors often save a file in several steps, and several files may be changed at once.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Build the spritesheet, and then rebuild it whenever an image in the input directories changes.
+///
+/// Only the sprites for images that have been added or changed are rendered again. Errors are
+/// reported without stopping, so that a half-saved or invalid image can be fixed and picked up by
+/// the next rebuild. `on_save` is called with the saved spritesheets after each successful build.
+/// Runs until the process is stopped.
+pub fn watch(args: &SpritesheetArgs, mut on_save: impl FnMut(Vec<SavedSpritesheet>)) -> ! {
+    // Clap requires the input and output arguments wherever spritesheet arguments are used.
+    let Some(output) = &args.output else {
+        unreachable!()
+    };
+    let inputs = describe_inputs(args);
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Error: could not watch {inputs} for changes ({e})");
+            std::process::exit(exitcode::OSERR);
+        }
+    };
+    let mode = if args.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    for input in &args.input {
+        if let Err(e) = watcher.watch(&input.path, mode) {
+            eprintln!("Error: could not watch {:?} for changes ({e})", input.path);
+            std::process::exit(exitcode::IOERR);
+        }
+    }
+    println!("Watching {inputs} for changes (press Ctrl+C to stop)");
+
+    let mut images = ImageCache::default();
+    let mut sprites = BTreeMap::new();
+    let mut versions = None;
+    loop {
+        // Events only say that something has changed, so compare the images' modification times
+        // and sizes to find out which of them have changed. This ignores files that aren't input
+        // images, such as hidden files and the spritesheet itself.
+        let current = file_versions(args);
+        let changed = match &versions {
+            Some(previous) => changed_paths(previous, &current),
+            None => current.keys().cloned().collect(),
+        };
+        if versions.is_none() || !changed.is_empty() {
+            for path in &changed {
+                images.forget(path);
+                sprites.remove(path);
+            }
+            match try_build(args, &mut images, &mut sprites) {
+                Ok(saved) => {
+                    println!("Saved {output}");
+                    on_save(saved);
+                }
+                Err(e) => eprintln!("{}", e.0),
+            }
+        }
+        versions = Some(current);
+
+        // Wait for something to change, and then for the changes to stop.
+        if receiver.recv().is_err() {
+            eprintln!("Error: stopped watching {inputs} for changes");
+            std::process::exit(exitcode::IOERR);
+        }
+        while receiver.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+}
+
+/// The modification time and size of a file, which change when the file is changed.
+type FileVersion = (SystemTime, u64);
+
+/// The modification time and size of each input image. Images whose modification time can't be
+/// read are included without them.
+fn file_versions(args: &SpritesheetArgs) -> BTreeMap<PathBuf, Option<FileVersion>> {
+    input_images(args)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(_, path)| {
+            let metadata = path.metadata();
+            let version = metadata.and_then(|m| Ok((m.modified()?, m.len()))).ok();
+            (path, version)
+        })
+        .collect()
+}
+
+/// The paths of images that have been added, changed or removed.
+fn changed_paths(
+    previous: &BTreeMap<PathBuf, Option<FileVersion>>,
+    current: &BTreeMap<PathBuf, Option<FileVersion>>,
+) -> Vec<PathBuf> {
+    let changed = current
+        .iter()
+        .filter(|&(path, version)| previous.get(path) != Some(version) || version.is_none());
+    let removed = previous
+        .iter()
+        .filter(|&(path, _)| !current.contains_key(path));
+    changed
+        .chain(removed)
+        .map(|(path, _)| path.clone())
+        .collect()
+}
@@ -2,8 +2,11 @@ use std::io;
 use std::path::PathBuf;
 
 use oxipng::PngError;
+use sdf_glyph_renderer::SdfGlyphError;
 use thiserror::Error;
 
+use crate::sprite::NameCollision;
+
 pub type SpreetResult<T> = Result<T, SpreetError>;
 
 /// Errors encountered during execution.
@@ -15,8 +18,41 @@ pub enum SpreetError {
     PathError(PathBuf),
     #[error("PNG encoding error: {0}")]
     PngError(#[from] png::EncodingError),
+    #[error("PNG decoding error: {0}")]
+    PngDecodingError(#[from] png::DecodingError),
+    #[error("WebP decoding error: {0}")]
+    WebPDecodingError(#[from] image_webp::DecodingError),
     #[error("Oxipng error: {0}")]
     OxiPngError(#[from] PngError),
     #[error("SVG error: {0}")]
     SvgError(#[from] resvg::usvg::Error),
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Input pattern error: {0}")]
+    PatternError(#[from] ignore::Error),
+    #[error("More than one image has the same sprite name: {}", list_collisions(.0))]
+    NameCollisionError(Vec<NameCollision>),
+    #[error("Sprite {0} lies outside the spritesheet")]
+    SpriteBoundsError(String),
+    #[error("There are no sprites to put in the spritesheet")]
+    EmptySpritesheetError,
+    #[error("Image has no width or height")]
+    ZeroSizedImageError,
+    #[error("Pixel ratio must be greater than zero")]
+    PixelRatioError,
+    #[error("Image of {width}x{height} pixels is too large to create")]
+    ImageTooLargeError { width: u32, height: u32 },
+    #[error("SDF error: {0}")]
+    SdfError(#[from] SdfGlyphError),
+    #[error("Could not pack the sprites into a spritesheet of at most {width}x{height} pixels")]
+    PackingError { width: u32, height: u32 },
+}
+
+/// List name collisions in an error message.
+fn list_collisions(collisions: &[NameCollision]) -> String {
+    let collisions = collisions
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    collisions.join(", ")
 }
@@ -1,23 +1,44 @@
-use std::fs::{read, read_dir, DirEntry};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{read, read_dir, remove_file, rename, write, DirEntry};
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, OnceLock};
 
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use image_webp::{DecodingError, WebPDecoder};
+use resvg::tiny_skia::{ColorU8, Pixmap};
 use resvg::usvg::fontdb::Database;
 use resvg::usvg::{Options, Tree};
 
-use crate::error::SpreetResult;
+use crate::error::{SpreetError, SpreetResult};
+use crate::sprite::{par_map, SpriteDescription};
 
 /// Returns `true` if `entry`'s file name starts with `.`, `false` otherwise.
 fn is_hidden(entry: &DirEntry) -> bool {
     entry
         .file_name()
         .to_str()
-        .map_or(false, |s| s.starts_with('.'))
+        .is_some_and(|s| s.starts_with('.'))
 }
 
 /// Returns `true` if `entry` is a file with the extension `.svg`, `false` otherwise.
 fn is_svg_file(entry: &DirEntry) -> bool {
-    entry.path().is_file() && entry.path().extension().map_or(false, |s| s == "svg")
+    entry.path().is_file() && entry.path().extension().is_some_and(|s| s == "svg")
+}
+
+/// Returns `true` if `path` has the extension `.png` or `.webp`, `false` otherwise.
+pub fn is_raster_path<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|s| s == "png" || s == "webp")
+}
+
+/// Returns `true` if `entry` is a file with the extension `.svg`, `.png` or `.webp`, `false`
+/// otherwise.
+fn is_image_file(entry: &DirEntry) -> bool {
+    is_svg_file(entry) || (entry.path().is_file() && is_raster_path(entry.path()))
 }
 
 /// Returns `true` if `entry` is an SVG image and isn't hidden.
@@ -25,22 +46,195 @@ fn is_useful_input(entry: &DirEntry) -> bool {
     !is_hidden(entry) && is_svg_file(entry)
 }
 
+/// Returns `true` if `entry` is an SVG, PNG or WebP image and isn't hidden.
+fn is_useful_image_input(entry: &DirEntry) -> bool {
+    !is_hidden(entry) && is_image_file(entry)
+}
+
+/// Options for finding the images in a directory, used by [`get_svg_input_paths_with_options`] and
+/// [`get_image_input_paths_with_options`].
+///
+/// Patterns use the same syntax as a `.gitignore` file: a pattern without a `/` (other than at its
+/// end) matches a file or directory with that name at any depth, a pattern with a `/` matches
+/// paths relative to the directory being searched, and a pattern ending with `/` only matches
+/// directories.
+#[derive(Clone, Debug, Default)]
+pub struct InputOptions {
+    /// Include images in sub-directories.
+    pub recursive: bool,
+    /// If not empty, only images matching at least one of these patterns (or in a directory that
+    /// matches one) are included.
+    pub include: Vec<String>,
+    /// Images and directories matching any of these patterns are left out. Directories that are
+    /// left out aren't searched at all.
+    pub exclude: Vec<String>,
+}
+
+/// The name of a file in an input directory that lists images and directories to leave out, in the
+/// same format as a `.gitignore` file.
+pub const IGNORE_FILE_NAME: &str = ".spreetignore";
+
 /// Returns a vector of file paths matching all SVGs within the given directory.
 ///
 /// It ignores hidden files (files whose names begin with `.`) but it does follow symlinks. If
-/// `recursive` is `true` it will also return file paths in sub-directories.
+/// `recursive` is `true` it will also return file paths in sub-directories. Any
+/// [`.spreetignore`](IGNORE_FILE_NAME) file is ignored; use [`get_svg_input_paths_with_options`]
+/// to leave out the images it lists.
 ///
 /// # Errors
 ///
 /// This function will return an error if Rust's underlying [`read_dir`] returns an error.
 pub fn get_svg_input_paths<P: AsRef<Path>>(path: P, recursive: bool) -> SpreetResult<Vec<PathBuf>> {
+    get_input_paths(
+        path.as_ref(),
+        &InputOptions {
+            recursive,
+            ..InputOptions::default()
+        },
+        false,
+        is_useful_input,
+    )
+}
+
+/// Returns a vector of file paths matching all SVGs within the given directory, including and
+/// excluding images and directories as described by `options`.
+///
+/// Hidden files are treated the same way as in [`get_svg_input_paths`]. Images and directories
+/// listed in a [`.spreetignore`](IGNORE_FILE_NAME) file in the directory are left out too.
+///
+/// # Errors
+///
+/// This function will return an error if Rust's underlying [`read_dir`] returns an error, if the
+/// `.spreetignore` file can't be read, or if a pattern isn't valid.
+pub fn get_svg_input_paths_with_options<P: AsRef<Path>>(
+    path: P,
+    options: &InputOptions,
+) -> SpreetResult<Vec<PathBuf>> {
+    get_input_paths(path.as_ref(), options, true, is_useful_input)
+}
+
+/// Returns a vector of file paths matching all SVG, PNG and WebP images within the given
+/// directory.
+///
+/// Hidden files, sub-directories and `.spreetignore` files are treated the same way as in
+/// [`get_svg_input_paths`]. Use [`load_svg`] or [`load_raster`] to load each image, depending on
+/// its extension (see [`is_raster_path`]).
+///
+/// # Errors
+///
+/// This function will return an error if Rust's underlying [`read_dir`] returns an error.
+pub fn get_image_input_paths<P: AsRef<Path>>(
+    path: P,
+    recursive: bool,
+) -> SpreetResult<Vec<PathBuf>> {
+    get_input_paths(
+        path.as_ref(),
+        &InputOptions {
+            recursive,
+            ..InputOptions::default()
+        },
+        false,
+        is_useful_image_input,
+    )
+}
+
+/// Returns a vector of file paths matching all SVG, PNG and WebP images within the given
+/// directory, including and excluding images and directories as described by `options`.
+///
+/// Hidden files, sub-directories and `.spreetignore` files are treated the same way as in
+/// [`get_svg_input_paths_with_options`].
+///
+/// # Errors
+///
+/// This function will return an error if Rust's underlying [`read_dir`] returns an error, if the
+/// `.spreetignore` file can't be read, or if a pattern isn't valid.
+pub fn get_image_input_paths_with_options<P: AsRef<Path>>(
+    path: P,
+    options: &InputOptions,
+) -> SpreetResult<Vec<PathBuf>> {
+    get_input_paths(path.as_ref(), options, true, is_useful_image_input)
+}
+
+/// The patterns that decide which images in an input directory are included.
+struct InputFilter {
+    include: Option<Gitignore>,
+    exclude: Gitignore,
+}
+
+impl InputFilter {
+    /// Build the filter for the input directory `root`, reading its `.spreetignore` file if it has
+    /// one and `use_ignore_file` is true.
+    fn new(root: &Path, options: &InputOptions, use_ignore_file: bool) -> SpreetResult<Self> {
+        let include = if options.include.is_empty() {
+            None
+        } else {
+            let mut builder = GitignoreBuilder::new(root);
+            for pattern in &options.include {
+                builder.add_line(None, pattern)?;
+            }
+            Some(builder.build()?)
+        };
+
+        let mut builder = GitignoreBuilder::new(root);
+        let ignore_file = root.join(IGNORE_FILE_NAME);
+        if use_ignore_file && ignore_file.is_file() {
+            if let Some(e) = builder.add(ignore_file) {
+                return Err(e.into());
+            }
+        }
+        for pattern in &options.exclude {
+            builder.add_line(None, pattern)?;
+        }
+        let exclude = builder.build()?;
+        Ok(Self { include, exclude })
+    }
+
+    /// Whether the image or directory at `path` is left out.
+    fn excludes(&self, path: &Path, is_dir: bool) -> bool {
+        self.exclude.matched(path, is_dir).is_ignore()
+    }
+
+    /// Whether the image at `path` is included by the include patterns, if there are any.
+    fn includes(&self, path: &Path) -> bool {
+        self.include.as_ref().map_or(true, |include| {
+            include.matched_path_or_any_parents(path, false).is_ignore()
+        })
+    }
+}
+
+/// Returns a vector of file paths within the given directory for which `is_input` returns `true`,
+/// and which aren't left out by `options` or, if `use_ignore_file` is true, its `.spreetignore`
+/// file.
+fn get_input_paths(
+    path: &Path,
+    options: &InputOptions,
+    use_ignore_file: bool,
+    is_input: fn(&DirEntry) -> bool,
+) -> SpreetResult<Vec<PathBuf>> {
+    let filter = InputFilter::new(path, options, use_ignore_file)?;
+    walk_input_paths(path, options.recursive, &filter, is_input)
+}
+
+/// Returns a vector of the file paths within the directory `path` that are included by `filter`.
+fn walk_input_paths(
+    path: &Path,
+    recursive: bool,
+    filter: &InputFilter,
+    is_input: fn(&DirEntry) -> bool,
+) -> SpreetResult<Vec<PathBuf>> {
     Ok(read_dir(path)?
         .filter_map(|entry| {
             if let Ok(entry) = entry {
                 let path_buf = entry.path();
                 if recursive && path_buf.is_dir() {
-                    get_svg_input_paths(path_buf, true).ok()
-                } else if is_useful_input(&entry) {
+                    if filter.excludes(&path_buf, true) {
+                        return None;
+                    }
+                    walk_input_paths(&path_buf, true, filter, is_input).ok()
+                } else if is_input(&entry)
+                    && !filter.excludes(&path_buf, false)
+                    && filter.includes(&path_buf)
+                {
                     Some(vec![path_buf])
                 } else {
                     None
@@ -55,6 +249,11 @@ pub fn get_svg_input_paths<P: AsRef<Path>>(path: P, recursive: bool) -> SpreetRe
 
 /// Load an SVG image from a file path.
 pub fn load_svg<P: AsRef<Path>>(path: P) -> SpreetResult<Tree> {
+    parse_svg(&read(&path)?, path.as_ref())
+}
+
+/// Parse an SVG image that was read from the file at `path`, in the same way as [`load_svg`].
+pub(crate) fn parse_svg(data: &[u8], path: &Path) -> SpreetResult<Tree> {
     static FONTDB: OnceLock<Arc<Database>> = OnceLock::new();
     let fontdb = FONTDB
         .get_or_init(|| {
@@ -64,17 +263,158 @@ pub fn load_svg<P: AsRef<Path>>(path: P) -> SpreetResult<Tree> {
         })
         .clone();
 
-    // The resources directory needs to be the same location as the SVG file itself, so that any
-    // embedded resources (like PNGs in <image> elements) that use relative URLs can be resolved
-    // correctly.
-    let resources_dir = std::fs::canonicalize(&path)
-        .ok()
-        .and_then(|p| p.parent().map(Path::to_path_buf));
     let options = Options {
-        resources_dir,
+        resources_dir: svg_resources_dir(path),
         fontdb,
         ..Options::default()
     };
 
-    Ok(Tree::from_data(&read(path)?, &options)?)
+    Ok(Tree::from_data(data, &options)?)
+}
+
+/// The directory that relative URLs in the SVG file at `path` are resolved against.
+///
+/// The resources directory needs to be the same location as the SVG file itself, so that any
+/// embedded resources (like PNGs in <image> elements) that use relative URLs can be resolved
+/// correctly.
+pub(crate) fn svg_resources_dir(path: &Path) -> Option<PathBuf> {
+    std::fs::canonicalize(path)
+        .ok()
+        .and_then(|p| p.parent().map(Path::to_path_buf))
+}
+
+/// Load a PNG or WebP image from a file path.
+///
+/// The image's pixel ratio can be read from its file name with [`raster_pixel_ratio`].
+///
+/// # Errors
+///
+/// This function will return an error if the file can't be read, if its extension isn't `.png` or
+/// `.webp`, or if the image can't be decoded.
+pub fn load_raster<P: AsRef<Path>>(path: P) -> SpreetResult<Pixmap> {
+    let data = read(&path)?;
+    match path.as_ref().extension() {
+        Some(ext) if ext == "png" => Ok(Pixmap::decode_png(&data)?),
+        Some(ext) if ext == "webp" => decode_webp(&data),
+        _ => Err(SpreetError::PathError(path.as_ref().to_path_buf())),
+    }
+}
+
+/// An input image loaded with [`load_image`].
+#[derive(Clone, Debug)]
+pub enum InputImage {
+    /// A parsed SVG image, loaded with [`load_svg`].
+    Svg(Box<Tree>),
+    /// A PNG or WebP image, loaded with [`load_raster`]. Its pixel ratio can be read from its file
+    /// name with [`raster_pixel_ratio`].
+    Raster(Pixmap),
+}
+
+/// Load an SVG, PNG or WebP image from a file path. Files with a `.png` or `.webp` extension (see
+/// [`is_raster_path`]) are loaded with [`load_raster`], and any other files with [`load_svg`].
+pub fn load_image<P: AsRef<Path>>(path: P) -> SpreetResult<InputImage> {
+    if is_raster_path(&path) {
+        load_raster(path).map(InputImage::Raster)
+    } else {
+        load_svg(path).map(|tree| InputImage::Svg(Box::new(tree)))
+    }
+}
+
+/// Load several images with [`load_image`], returning the result for each path, in the same order
+/// as `paths`. With the `parallel` feature, the images are loaded on several threads.
+///
+/// Every image is loaded, even if some of them can't be, so that all the invalid images in a set
+/// of icons can be reported at once rather than one at a time.
+pub fn load_images<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<(PathBuf, SpreetResult<InputImage>)> {
+    par_map(paths, |path| {
+        (path.as_ref().to_path_buf(), load_image(path))
+    })
+}
+
+/// Decode an in-memory WebP image into a bitmap with premultiplied alpha.
+///
+/// Animated WebP images are decoded as their first frame.
+fn decode_webp(data: &[u8]) -> SpreetResult<Pixmap> {
+    let mut decoder = WebPDecoder::new(Cursor::new(data))?;
+    let (width, height) = decoder.dimensions();
+    let channels = if decoder.has_alpha() { 4 } else { 3 };
+    let mut buf = vec![
+        0;
+        decoder
+            .output_buffer_size()
+            .ok_or(DecodingError::ImageTooLarge)?
+    ];
+    decoder.read_image(&mut buf)?;
+    let mut pixmap = Pixmap::new(width, height).ok_or(DecodingError::ImageTooLarge)?;
+    for (pixel, rgba) in pixmap
+        .pixels_mut()
+        .iter_mut()
+        .zip(buf.chunks_exact(channels))
+    {
+        let alpha = rgba.get(3).copied().unwrap_or(u8::MAX);
+        *pixel = ColorU8::from_rgba(rgba[0], rgba[1], rgba[2], alpha).premultiply();
+    }
+    Ok(pixmap)
+}
+
+/// Returns the pixel ratio of a PNG or WebP image, taken from an `@2x`-style suffix on its file
+/// name. Images without a suffix have a pixel ratio of 1.
+///
+/// This follows the same convention as [`ratio_file_prefix`](crate::ratio_file_prefix), so an
+/// image named `icon@2x.png` has a pixel ratio of 2, and the sprite created from it is named `icon`
+/// (see [`sprite_name`](crate::sprite_name)).
+pub fn raster_pixel_ratio<P: AsRef<Path>>(path: P) -> u8 {
+    path.as_ref()
+        .file_stem()
+        .and_then(|stem| split_ratio_suffix(&stem.to_string_lossy()).1)
+        .unwrap_or(1)
+}
+
+/// Split a file stem like `icon@2x` into the name `icon` and the pixel ratio `2`.
+///
+/// Returns the whole stem and no ratio if the stem doesn't end in a valid, non-zero ratio suffix.
+pub(crate) fn split_ratio_suffix(stem: &str) -> (&str, Option<u8>) {
+    let ratio = stem
+        .rsplit_once('@')
+        .and_then(|(name, suffix)| Some((name, suffix.strip_suffix('x')?.parse::<u8>().ok()?)))
+        .filter(|&(name, ratio)| !name.is_empty() && ratio > 0);
+    match ratio {
+        Some((name, ratio)) => (name, Some(ratio)),
+        None => (stem, None),
+    }
+}
+
+/// Load a spritesheet index from a JSON file, such as one saved with
+/// [`Spritesheet::save_index`](crate::Spritesheet::save_index).
+///
+/// Simple index files (see
+/// [`Spritesheet::save_index_simple`](crate::Spritesheet::save_index_simple)) can be loaded too, in
+/// which case each sprite's pixel ratio is assumed to be 1.
+pub fn load_index<P: AsRef<Path>>(path: P) -> SpreetResult<BTreeMap<String, SpriteDescription>> {
+    Ok(serde_json::from_slice(&read(path)?)?)
+}
+
+/// Write `contents` to a file, replacing it atomically if it already exists.
+///
+/// The contents are written to a hidden temporary file in the same directory, which is then renamed
+/// to `path`. A program reading the file sees either its old contents or its new contents, never a
+/// partly written file. Each call uses a temporary file of its own, so several threads or
+/// processes can write the same file at once, and the last one to finish wins.
+pub fn write_atomically<P: AsRef<Path>>(path: P, contents: &[u8]) -> std::io::Result<()> {
+    static TEMP_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+    let path = path.as_ref();
+    let file_name = path.file_name().ok_or(std::io::ErrorKind::InvalidInput)?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TEMP_FILE_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+    write(&temp_path, contents)
+        .and_then(|()| rename(&temp_path, path))
+        .inspect_err(|_| {
+            let _ = remove_file(&temp_path);
+        })
 }
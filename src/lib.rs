@@ -10,3 +10,6 @@ pub use fs::*;
 
 mod sprite;
 pub use sprite::*;
+
+mod style;
+pub use style::*;
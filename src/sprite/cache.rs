@@ -0,0 +1,454 @@
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use resvg::tiny_skia::{IntSize, Pixmap};
+use resvg::usvg::{decompress_svgz, roxmltree, Rect, Tree};
+use siphasher::sip128::{Hasher128, SipHasher13};
+
+use super::{SdfOptions, Sprite, SpriteCenter, SpriteSource};
+use crate::error::SpreetResult;
+use crate::fs::{load_svg, parse_svg, svg_resources_dir, write_atomically};
+
+/// A directory of rendered sprites, so that an SVG image is only parsed and rendered again when it
+/// changes.
+///
+/// Each sprite is stored in a file named after a hash of its SVG file, the contents of any images
+/// the SVG links to with `<image>` elements, the [options](RenderOptions) it was rendered with, and
+/// the version of Spreet. The cache is checked before the SVG is parsed, so a sprite that's in the
+/// cache is loaded without parsing or rendering its SVG, which speeds up builds of large sets of
+/// images where only a few have changed. Fonts aren't part of the hash, so empty the cache after
+/// changing the fonts used by SVGs with text. Files are never removed from the cache, so delete the
+/// directory to empty it.
+#[derive(Clone, Debug)]
+pub struct RenderCache {
+    dir: PathBuf,
+    /// Errors from storing sprites in the cache, shared by all the copies of the cache.
+    store_errors: Arc<Mutex<Vec<std::io::Error>>>,
+}
+
+/// How an SVG image is rendered as a sprite, by [`RenderOptions::render`] or a [`RenderCache`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// The sprite's pixel ratio.
+    pub pixel_ratio: u8,
+    /// Options for the sprite's signed distance field, or `None` for a sprite that isn't an SDF
+    /// sprite. See [`Sprite::new_sdf_with_options`].
+    pub sdf: Option<SdfOptions>,
+    /// Whether to [crop](Sprite::crop) the sprite to remove transparent edges.
+    pub crop: bool,
+    /// Whether to record the position of the sprite's center before it's cropped.
+    pub include_center: bool,
+}
+
+/// The SVG image of a sprite from a [`RenderCache`], which is only parsed if the sprite has to be
+/// rendered.
+#[derive(Clone)]
+pub(super) struct CachedSvg {
+    /// The path of the SVG file.
+    path: PathBuf,
+    /// A hash of the SVG file and the images it links to. See [`source_key`].
+    source_key: u128,
+    /// The parsed SVG image, if the sprite was rendered rather than loaded from the cache.
+    pub(super) tree: Option<Box<Tree>>,
+    /// The sprite's content area, in the sprite's pixels. See [`Sprite::content_area`].
+    pub(super) content: Option<Rect>,
+    /// The sprite's horizontal stretch areas, in the sprite's pixels.
+    pub(super) stretch_x: Option<Vec<Rect>>,
+    /// The sprite's vertical stretch areas, in the sprite's pixels.
+    pub(super) stretch_y: Option<Vec<Rect>>,
+}
+
+/// A sprite as it's stored in a cache file.
+struct Entry {
+    pixmap: Pixmap,
+    center: Option<SpriteCenter>,
+    content: Option<Rect>,
+    stretch_x: Option<Vec<Rect>>,
+    stretch_y: Option<Vec<Rect>>,
+}
+
+impl RenderOptions {
+    /// Render an SVG image as a sprite, without a cache.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the sprite can't be created (see [`Sprite::new`] and
+    /// [`Sprite::new_sdf`]).
+    pub fn render(&self, tree: Tree) -> SpreetResult<Sprite> {
+        let mut sprite = match self.sdf {
+            Some(sdf_options) => Sprite::new_sdf_with_options(tree, self.pixel_ratio, sdf_options)?,
+            None => Sprite::new(tree, self.pixel_ratio)?,
+        };
+        if self.crop {
+            sprite.crop(self.include_center);
+        }
+        Ok(sprite)
+    }
+
+    /// The options that `sprite`, rendered from an SVG image, would be rendered with again at
+    /// `pixel_ratio`.
+    fn for_sprite(sprite: &Sprite, pixel_ratio: u8) -> Self {
+        Self {
+            pixel_ratio,
+            sdf: sprite.sdf.then_some(sprite.sdf_options),
+            crop: sprite.cropped,
+            include_center: sprite.center.is_some(),
+        }
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            pixel_ratio: 1,
+            sdf: None,
+            crop: false,
+            include_center: false,
+        }
+    }
+}
+
+impl CachedSvg {
+    /// The parsed SVG image, which is loaded from its file if the sprite came from the cache.
+    pub(super) fn load_tree(&self) -> SpreetResult<Tree> {
+        match &self.tree {
+            Some(tree) => Ok(Tree::clone(tree)),
+            None => load_svg(&self.path),
+        }
+    }
+}
+
+impl RenderCache {
+    /// Store rendered sprites in `dir`, which is created when the first sprite is stored.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            store_errors: Arc::default(),
+        }
+    }
+
+    /// The directory the sprites are stored in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Render the SVG file at `path` as a sprite, or load the sprite from the cache if the same
+    /// image has been rendered with the same options before. Newly rendered sprites are stored in
+    /// the cache.
+    ///
+    /// The sprite has the same bitmap, center and stretchable icon metadata as one created with
+    /// [`load_svg`](crate::load_svg) and [`RenderOptions::render`]. A sprite loaded from the cache
+    /// has no [SVG tree](Sprite::svg_tree), because its SVG image isn't parsed.
+    ///
+    /// A sprite that can't be stored in the cache is still returned, because the cache only saves
+    /// work in later builds. The error can be found with [`Self::take_store_errors`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the SVG file can't be read, or if the sprite isn't in
+    /// the cache and can't be parsed or created.
+    pub fn render<P: AsRef<Path>>(&self, path: P, options: &RenderOptions) -> SpreetResult<Sprite> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+        let source_key = source_key(path, &data);
+        self.render_source(path, source_key, options, || parse_svg(&data, path))
+    }
+
+    /// Create a copy of a sprite rendered at a different pixel ratio, like
+    /// [`Sprite::with_pixel_ratio`], but using the cache if the sprite came from a `RenderCache`.
+    /// Other sprites are rendered or resampled without the cache.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the new sprite can't be created.
+    pub fn with_pixel_ratio(&self, sprite: &Sprite, pixel_ratio: u8) -> SpreetResult<Sprite> {
+        match &sprite.source {
+            SpriteSource::CachedSvg(svg) if pixel_ratio != sprite.pixel_ratio => {
+                let options = RenderOptions::for_sprite(sprite, pixel_ratio);
+                match &svg.tree {
+                    Some(tree) => self.render_source(&svg.path, svg.source_key, &options, || {
+                        Ok(Tree::clone(tree))
+                    }),
+                    // The file is read again in case it's changed since the sprite was loaded.
+                    None => self.render(&svg.path, &options),
+                }
+            }
+            _ => sprite.with_pixel_ratio(pixel_ratio),
+        }
+    }
+
+    /// Take the errors from sprites that couldn't be stored in the cache (or in any copy of it)
+    /// since this was last called.
+    pub fn take_store_errors(&self) -> Vec<std::io::Error> {
+        let mut store_errors = self
+            .store_errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        std::mem::take(&mut *store_errors)
+    }
+
+    /// Load the sprite for an SVG image with the hash `source_key` from the cache, or render the
+    /// image returned by `load` and store it in the cache.
+    fn render_source(
+        &self,
+        path: &Path,
+        source_key: u128,
+        options: &RenderOptions,
+        load: impl FnOnce() -> SpreetResult<Tree>,
+    ) -> SpreetResult<Sprite> {
+        let entry_path = self.dir.join(cache_key(source_key, options));
+        // A file that can't be read, or isn't a valid entry, is replaced.
+        if let Some(entry) = std::fs::read(&entry_path).ok().and_then(decode_entry) {
+            return Ok(entry.into_sprite(path, source_key, None, options));
+        }
+        let sprite = options.render(load()?)?;
+        let entry = Entry {
+            content: sprite.content_area(),
+            stretch_x: sprite.stretch_x_areas(),
+            stretch_y: sprite.stretch_y_areas(),
+            pixmap: sprite.pixmap,
+            center: sprite.center,
+        };
+        let stored = std::fs::create_dir_all(&self.dir)
+            .and_then(|()| write_atomically(&entry_path, &encode_entry(&entry)));
+        if let Err(e) = stored {
+            self.store_errors
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(e);
+        }
+        let tree = match sprite.source {
+            SpriteSource::Svg(tree) => Some(tree),
+            _ => None,
+        };
+        Ok(entry.into_sprite(path, source_key, tree, options))
+    }
+}
+
+impl Entry {
+    /// The sprite for the SVG file at `path`, rendered with `options`.
+    fn into_sprite(
+        self,
+        path: &Path,
+        source_key: u128,
+        tree: Option<Box<Tree>>,
+        options: &RenderOptions,
+    ) -> Sprite {
+        Sprite {
+            source: SpriteSource::CachedSvg(Box::new(CachedSvg {
+                path: path.to_path_buf(),
+                source_key,
+                tree,
+                content: self.content,
+                stretch_x: self.stretch_x,
+                stretch_y: self.stretch_y,
+            })),
+            pixel_ratio: options.pixel_ratio,
+            pixmap: self.pixmap,
+            center: self.center,
+            sdf: options.sdf.is_some(),
+            sdf_options: options.sdf.unwrap_or_default(),
+            cropped: options.crop,
+        }
+    }
+}
+
+/// The version of the cache file format, which is part of every cache key so that files in an
+/// older format aren't read.
+const ENTRY_FORMAT: u8 = 2;
+
+/// A hash of the SVG file at `path`, whose contents are `data`, and of the contents of any images
+/// it links to.
+fn source_key(path: &Path, data: &[u8]) -> u128 {
+    let mut hasher = SipHasher13::new();
+    write_bytes(&mut hasher, data);
+    let linked_images = linked_images(data);
+    if !linked_images.is_empty() {
+        // Relative URLs are resolved in the same way as when the SVG is parsed.
+        let resources_dir = svg_resources_dir(path).unwrap_or_default();
+        for href in linked_images {
+            write_bytes(&mut hasher, href.as_bytes());
+            match std::fs::read(resources_dir.join(&href)) {
+                Ok(image) => {
+                    hasher.write_u8(1);
+                    write_bytes(&mut hasher, &image);
+                }
+                Err(_) => hasher.write_u8(0),
+            }
+        }
+    }
+    hasher.finish128().as_u128()
+}
+
+/// The URLs of the images that an SVG file links to with `<image>` elements, rather than embedding
+/// them with `data:` URLs.
+fn linked_images(data: &[u8]) -> Vec<String> {
+    // Most SVGs don't link to anything, and don't need to be parsed.
+    if !data.starts_with(&[0x1f, 0x8b]) && !data.windows(4).any(|bytes| bytes == b"href") {
+        return Vec::new();
+    }
+    let decompressed;
+    let data = if data.starts_with(&[0x1f, 0x8b]) {
+        match decompress_svgz(data) {
+            Ok(data) => {
+                decompressed = data;
+                &decompressed
+            }
+            Err(_) => return Vec::new(),
+        }
+    } else {
+        data
+    };
+    let xml_options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    // An SVG that can't be parsed isn't rendered, so it doesn't matter what it links to.
+    let Some(document) = std::str::from_utf8(data)
+        .ok()
+        .and_then(|text| roxmltree::Document::parse_with_options(text, xml_options).ok())
+    else {
+        return Vec::new();
+    };
+    document
+        .descendants()
+        .filter(|node| node.tag_name().name() == "image")
+        .filter_map(|node| node.attributes().find(|attr| attr.name() == "href"))
+        .map(|href| href.value().trim())
+        .filter(|href| !href.to_ascii_lowercase().starts_with("data:"))
+        .map(String::from)
+        .collect()
+}
+
+/// The name of the cache file for an SVG image with the hash `source_key` rendered with
+/// `options`: a 128-bit hash, in hex.
+fn cache_key(source_key: u128, options: &RenderOptions) -> String {
+    let mut hasher = SipHasher13::new();
+    write_bytes(&mut hasher, env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.write_u8(ENTRY_FORMAT);
+    hasher.write_u128(source_key);
+    hasher.write_u8(options.pixel_ratio);
+    match options.sdf {
+        Some(SdfOptions {
+            buffer,
+            radius,
+            cutoff,
+        }) => {
+            hasher.write_u8(1);
+            hasher.write_u8(buffer);
+            hasher.write_u8(radius);
+            hasher.write_u64(cutoff.to_bits());
+        }
+        None => hasher.write_u8(0),
+    }
+    // The center is only recorded when the sprite is cropped.
+    hasher.write_u8(u8::from(options.crop));
+    hasher.write_u8(u8::from(options.crop && options.include_center));
+    format!("{:032x}", hasher.finish128().as_u128())
+}
+
+/// Add some bytes, preceded by their length, to a hash.
+fn write_bytes(hasher: &mut SipHasher13, data: &[u8]) {
+    hasher.write_u64(data.len() as u64);
+    hasher.write(data);
+}
+
+/// Encode a sprite as the contents of a cache file: its width and height, its center, its content
+/// area, its stretch areas, and then its pixels. Optional values are preceded by a byte that's 1
+/// if they're present, and lists by their length. The pixels are stored exactly as they are in
+/// memory (premultiplied RGBA), rather than as a PNG image, so that a cached sprite is identical
+/// to a newly rendered one.
+fn encode_entry(entry: &Entry) -> Vec<u8> {
+    let pixmap = &entry.pixmap;
+    let mut data = Vec::with_capacity(pixmap.data().len() + 64);
+    data.extend(pixmap.width().to_le_bytes());
+    data.extend(pixmap.height().to_le_bytes());
+    let center = entry.center.map(|SpriteCenter { x, y }| [x, y]);
+    data.push(u8::from(center.is_some()));
+    data.extend(
+        center
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|n| n.to_le_bytes()),
+    );
+    data.push(u8::from(entry.content.is_some()));
+    data.extend(entry.content.iter().flat_map(rect_bytes));
+    for areas in [&entry.stretch_x, &entry.stretch_y] {
+        let areas = areas.as_deref().unwrap_or_default();
+        data.extend((areas.len() as u32).to_le_bytes());
+        data.extend(areas.iter().flat_map(rect_bytes));
+    }
+    data.extend(pixmap.data());
+    data
+}
+
+/// The edges of a rectangle, as they're stored in a cache file.
+fn rect_bytes(rect: &Rect) -> impl Iterator<Item = u8> {
+    [rect.left(), rect.top(), rect.right(), rect.bottom()]
+        .into_iter()
+        .flat_map(f32::to_le_bytes)
+}
+
+/// Decode the contents of a cache file created by [`encode_entry`], returning `None` if it isn't
+/// valid.
+fn decode_entry(data: Vec<u8>) -> Option<Entry> {
+    let mut reader = EntryReader(&data);
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    let has_center = reader.u8()? != 0;
+    let center = SpriteCenter {
+        x: reader.f32()?,
+        y: reader.f32()?,
+    };
+    let content = match reader.u8()? {
+        0 => None,
+        _ => Some(reader.rect()?),
+    };
+    let stretch_x = reader.rects()?;
+    let stretch_y = reader.rects()?;
+    let pixmap = Pixmap::from_vec(reader.0.to_vec(), IntSize::from_wh(width, height)?)?;
+    Some(Entry {
+        pixmap,
+        center: has_center.then_some(center),
+        content,
+        stretch_x,
+        stretch_y,
+    })
+}
+
+/// Reads the values at the start of a cache file in turn.
+struct EntryReader<'a>(&'a [u8]);
+
+impl EntryReader<'_> {
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.0.split_first_chunk()?;
+        self.0 = rest;
+        Some(*bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes().map(u8::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.bytes().map(f32::from_le_bytes)
+    }
+
+    fn rect(&mut self) -> Option<Rect> {
+        Rect::from_ltrb(self.f32()?, self.f32()?, self.f32()?, self.f32()?)
+    }
+
+    /// A list of stretch areas, which is `None` if it's empty, in the same way as
+    /// [`Sprite::stretch_x_areas`].
+    fn rects(&mut self) -> Option<Option<Vec<Rect>>> {
+        let areas = (0..self.u32()?)
+            .map(|_| self.rect())
+            .collect::<Option<Vec<_>>>()?;
+        Some((!areas.is_empty()).then_some(areas))
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+
+use resvg::tiny_skia::{Pixmap, PixmapPaint, PremultipliedColorU8, Transform};
+
+use super::{par_map, Sprite, Spritesheet};
+
+/// The differences between two spritesheets, found with [`Spritesheet::diff`].
+///
+/// Sprites are matched by name. A sprite that's only in the new spritesheet, but has exactly the
+/// same image as a sprite that's only in the old spritesheet, is counted as renamed rather than
+/// added and removed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpritesheetDiff {
+    /// Names of the sprites that are only in the new spritesheet.
+    pub added: Vec<String>,
+    /// Names of the sprites that are only in the old spritesheet.
+    pub removed: Vec<String>,
+    /// Sprites whose image has moved to a new name, as `(old name, new name)`.
+    pub renamed: Vec<(String, String)>,
+    /// Sprites whose width or height has changed.
+    pub resized: Vec<ResizedSprite>,
+    /// Sprites that are the same size but have different pixels.
+    pub changed: Vec<ChangedSprite>,
+}
+
+/// A sprite whose width or height differs between two spritesheets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResizedSprite {
+    pub name: String,
+    /// The width and height of the sprite in the old spritesheet.
+    pub old_size: (u32, u32),
+    /// The width and height of the sprite in the new spritesheet.
+    pub new_size: (u32, u32),
+}
+
+/// A sprite whose pixels differ between two spritesheets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangedSprite {
+    pub name: String,
+    /// How different the sprite's pixels are, from 0 (identical) to 1 (every colour channel of
+    /// every pixel is as different as it can be).
+    pub difference: f32,
+}
+
+/// Two sprites with the same size and almost the same pixels, found by [`near_duplicate_sprites`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct NearDuplicate {
+    /// The name of one of the sprites.
+    pub first: String,
+    /// The name of the other sprite, which comes after `first` in alphabetical order.
+    pub second: String,
+    /// How different the sprites' pixels are, from 0 to 1, in the same way as
+    /// [`ChangedSprite::difference`].
+    pub difference: f32,
+    /// The number of pixels that differ.
+    pub differing_pixels: usize,
+}
+
+impl SpritesheetDiff {
+    /// Whether the spritesheets have no differences.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.resized.is_empty()
+            && self.changed.is_empty()
+    }
+
+    /// Draw an image of the differences between the `old` and `new` spritesheets that this diff was
+    /// made from.
+    ///
+    /// The image has a row for each removed, added, resized or changed sprite, in that order. Each
+    /// row shows the old sprite, then the new sprite, and then, for changed sprites, the pixels
+    /// that differ in red over a faint copy of the new sprite. Renamed sprites aren't shown,
+    /// because their images are the same.
+    ///
+    /// Returns `None` if there are no sprites to show.
+    pub fn image(&self, old: &Spritesheet, new: &Spritesheet) -> Option<Pixmap> {
+        let rows = self
+            .removed
+            .iter()
+            .map(|name| [old.sprite_pixmap(name), None, None])
+            .chain(
+                self.added
+                    .iter()
+                    .map(|name| [None, new.sprite_pixmap(name), None]),
+            )
+            .chain(self.resized.iter().map(|resized| {
+                let name = &resized.name;
+                [old.sprite_pixmap(name), new.sprite_pixmap(name), None]
+            }))
+            .chain(self.changed.iter().map(|changed| {
+                let old = old.sprite_pixmap(&changed.name);
+                let new = new.sprite_pixmap(&changed.name);
+                let mask = old.as_ref().zip(new.as_ref()).and_then(difference_mask);
+                [old, new, mask]
+            }))
+            .collect::<Vec<_>>();
+
+        // Lay the rows out as a grid, with a gap between the cells.
+        const GAP: u32 = 4;
+        let column_width = rows.iter().flatten().flatten().map(Pixmap::width).max()?;
+        let row_heights = rows
+            .iter()
+            .map(|row| row.iter().flatten().map(Pixmap::height).max().unwrap_or(0))
+            .collect::<Vec<_>>();
+        let width = 3 * column_width + 4 * GAP;
+        let height = row_heights.iter().map(|h| h + GAP).sum::<u32>() + GAP;
+        let mut image = Pixmap::new(width, height)?;
+        let mut y = GAP;
+        for (row, row_height) in rows.iter().zip(row_heights) {
+            for (column, cell) in row.iter().enumerate() {
+                if let Some(cell) = cell {
+                    let x = GAP + column as u32 * (column_width + GAP);
+                    image.draw_pixmap(
+                        x as i32,
+                        y as i32,
+                        cell.as_ref(),
+                        &PixmapPaint::default(),
+                        Transform::identity(),
+                        None,
+                    );
+                }
+            }
+            y += row_height + GAP;
+        }
+        Some(image)
+    }
+}
+
+impl Spritesheet {
+    /// Compare this spritesheet with a `new` one, finding the sprites that have been added,
+    /// removed, renamed, resized or changed.
+    ///
+    /// Sprites are compared by their pixels, so sprites that have moved within the spritesheet, or
+    /// whose metadata has changed, aren't reported. Each changed sprite is given a score for how
+    /// different it is (see [`ChangedSprite::difference`]), which can be used to ignore small
+    /// changes such as differences in anti-aliasing.
+    pub fn diff(&self, new: &Spritesheet) -> SpritesheetDiff {
+        let mut diff = SpritesheetDiff::default();
+        for name in self.index.keys() {
+            let Some(new_description) = new.index.get(name) else {
+                diff.removed.push(name.clone());
+                continue;
+            };
+            let old_description = &self.index[name];
+            let old_size = (old_description.width, old_description.height);
+            let new_size = (new_description.width, new_description.height);
+            if old_size != new_size {
+                diff.resized.push(ResizedSprite {
+                    name: name.clone(),
+                    old_size,
+                    new_size,
+                });
+                continue;
+            }
+            let difference = match (self.sprite_pixmap(name), new.sprite_pixmap(name)) {
+                (Some(old), Some(new)) => difference(&old, &new),
+                // Both sprites are empty.
+                _ => 0.0,
+            };
+            if difference > 0.0 {
+                diff.changed.push(ChangedSprite {
+                    name: name.clone(),
+                    difference,
+                });
+            }
+        }
+        for name in new.index.keys() {
+            if !self.index.contains_key(name) {
+                diff.added.push(name.clone());
+            }
+        }
+
+        // Match removed sprites with added sprites that have the same image.
+        let mut removed = Vec::new();
+        for old_name in std::mem::take(&mut diff.removed) {
+            let old_pixmap = self.sprite_pixmap(&old_name);
+            let renamed_to = diff
+                .added
+                .iter()
+                .position(|new_name| new.sprite_pixmap(new_name) == old_pixmap);
+            match renamed_to {
+                Some(i) => diff.renamed.push((old_name, diff.added.remove(i))),
+                None => removed.push(old_name),
+            }
+        }
+        diff.removed = removed;
+        diff
+    }
+}
+
+/// Find pairs of sprites that are almost, but not exactly, the same: they have the same width and
+/// height, and their pixels are no more than `max_difference` different (see
+/// [`ChangedSprite::difference`]).
+///
+/// These are often copies of an icon that were exported separately and differ only in the
+/// anti-aliasing of a few pixels at their edges. Unlike identical sprites, which can share one
+/// image with [`SpritesheetBuilder::make_unique`](super::SpritesheetBuilder::make_unique), each of
+/// them takes up space in the spritesheet, so they may be worth replacing with a single icon.
+///
+/// Pairs are returned in order of their names.
+pub fn near_duplicate_sprites(
+    sprites: &BTreeMap<String, Sprite>,
+    max_difference: f32,
+) -> Vec<NearDuplicate> {
+    // Only sprites with the same size are compared.
+    let mut sprites_by_size = BTreeMap::<_, Vec<_>>::new();
+    for (name, sprite) in sprites {
+        let pixmap = sprite.pixmap();
+        sprites_by_size
+            .entry((pixmap.width(), pixmap.height()))
+            .or_default()
+            .push((name, pixmap));
+    }
+    // Each sprite is compared with the sprites of the same size that come after it, so the pairs
+    // are never all listed at once.
+    let firsts = sprites_by_size
+        .values()
+        .flat_map(|same_size| (0..same_size.len()).map(move |i| (same_size, i)))
+        .collect::<Vec<_>>();
+    let mut near_duplicates = par_map(&firsts, |&(same_size, i)| {
+        let (first, old) = same_size[i];
+        same_size[i + 1..]
+            .iter()
+            .filter_map(|&(second, new)| {
+                let (difference, differing_pixels) = compare(old, new, max_difference)?;
+                (difference > 0.0).then(|| NearDuplicate {
+                    first: first.clone(),
+                    second: second.clone(),
+                    difference,
+                    differing_pixels,
+                })
+            })
+            .collect::<Vec<_>>()
+    })
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    near_duplicates.sort_by(|a, b| (&a.first, &a.second).cmp(&(&b.first, &b.second)));
+    near_duplicates
+}
+
+/// The [`difference`] between two images of the same size and the number of pixels that differ, or
+/// `None` if they're more than `max_difference` different.
+///
+/// The images are compared a row at a time, and the comparison stops as soon as they're too
+/// different, so very different images are rejected without comparing all their pixels.
+fn compare(old: &Pixmap, new: &Pixmap, max_difference: f32) -> Option<(f32, usize)> {
+    let row_len = old.width() as usize * 4;
+    let rows = old.data().chunks(row_len).zip(new.data().chunks(row_len));
+    let mut total = 0;
+    let mut differing_pixels = 0;
+    for (old_row, new_row) in rows {
+        for (old_pixel, new_pixel) in old_row.chunks_exact(4).zip(new_row.chunks_exact(4)) {
+            if old_pixel != new_pixel {
+                differing_pixels += 1;
+                total += channel_difference(old_pixel, new_pixel);
+            }
+        }
+        // The difference can only grow with each row.
+        if mean_difference(total, old.data().len()) > max_difference {
+            return None;
+        }
+    }
+    Some((mean_difference(total, old.data().len()), differing_pixels))
+}
+
+/// How different two images of the same size are, from 0 to 1: the mean difference between their
+/// colour channels.
+fn difference(old: &Pixmap, new: &Pixmap) -> f32 {
+    let total = channel_difference(old.data(), new.data());
+    mean_difference(total, old.data().len())
+}
+
+/// The sum of the differences between the colour channels in `old` and `new`.
+fn channel_difference(old: &[u8], new: &[u8]) -> u64 {
+    old.iter()
+        .zip(new)
+        .map(|(&a, &b)| a.abs_diff(b) as u64)
+        .sum()
+}
+
+/// The mean difference, from 0 to 1, of `channels` colour channels whose differences add up to
+/// `total`.
+fn mean_difference(total: u64, channels: usize) -> f32 {
+    let max = channels as u64 * u8::MAX as u64;
+    if max > 0 {
+        (total as f64 / max as f64) as f32
+    } else {
+        0.0
+    }
+}
+
+/// An image of the pixels that differ between two images of the same size, shown in red over a
+/// faint grey copy of the `new` image.
+fn difference_mask((old, new): (&Pixmap, &Pixmap)) -> Option<Pixmap> {
+    let mut mask = Pixmap::new(new.width(), new.height())?;
+    let pixels = old.pixels().iter().zip(new.pixels());
+    for (mask_pixel, (old_pixel, new_pixel)) in mask.pixels_mut().iter_mut().zip(pixels) {
+        let (r, g, b, a) = if old_pixel == new_pixel {
+            // Premultiplied grey at a quarter of the new pixel's opacity.
+            let alpha = new_pixel.alpha() / 4;
+            (alpha / 2, alpha / 2, alpha / 2, alpha)
+        } else {
+            (u8::MAX, 0, 0, u8::MAX)
+        };
+        *mask_pixel = PremultipliedColorU8::from_rgba(r, g, b, a)?;
+    }
+    Some(mask)
+}
@@ -1,52 +1,110 @@
-use std::collections::btree_map::Entry;
-use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::Write;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::num::NonZero;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crunch::{Item, PackedItem, PackedItems, Rotation};
+use crunch::PackedItem;
 use multimap::MultiMap;
 use oxipng::optimize_from_memory;
-use resvg::tiny_skia::{Color, Pixmap, PixmapPaint, Transform};
+use resvg::tiny_skia::{Color, FilterQuality, IntRect, Pixmap, PixmapPaint, Transform};
 use resvg::usvg::{Rect, Tree};
 use sdf_glyph_renderer::{clamp_to_u8, BitmapGlyph};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use self::serialize::{serialize_rect, serialize_stretch_x_area, serialize_stretch_y_area};
+use self::cache::CachedSvg;
+pub use self::cache::{RenderCache, RenderOptions};
+pub use self::diff::{
+    near_duplicate_sprites, ChangedSprite, NearDuplicate, ResizedSprite, SpritesheetDiff,
+};
+use self::pack::{pack_bin, Layout};
+pub use self::pack::{CrunchPacker, MaxRectsPacker, Packer, ShelfPacker, SkylinePacker};
+use self::serialize::{
+    default_pixel_ratio, deserialize_rect, deserialize_stretch_x_area, deserialize_stretch_y_area,
+    serialize_rect, serialize_stretch_x_area, serialize_stretch_y_area,
+};
+pub use self::validate::{validate_index, validate_spritesheet, IndexProblem, IndexProblemKind};
 pub use crate::error::{SpreetError, SpreetResult};
+use crate::fs::{is_raster_path, split_ratio_suffix, write_atomically};
 
+mod cache;
+mod diff;
+mod pack;
+mod report;
 mod serialize;
+mod validate;
 
 /// A single icon within a spritesheet.
 ///
 /// A sprite is a rectangular icon stored as an SVG image and converted to a bitmap. The bitmap is
-/// saved to a spritesheet.
+/// saved to a spritesheet. A sprite can also be created from a PNG or WebP image, or sliced from an
+/// existing spritesheet, in which case there's no SVG image and the bitmap is resampled from the
+/// source bitmap.
 #[derive(Clone)]
 pub struct Sprite {
-    /// Parsed source SVG image.
-    tree: Tree,
+    /// Source image the bitmap is generated from.
+    source: SpriteSource,
     /// Ratio determining the size the destination pixels compared to the source pixels. A ratio of
     /// 2 means the bitmap will be scaled to be twice the size of the SVG image.
     pixel_ratio: u8,
-    /// Bitmap image generated from the SVG image.
+    /// Bitmap image generated from the source image.
     pixmap: Pixmap,
     /// Center of the image before post-render cropping.
     center: Option<SpriteCenter>,
+    /// Whether the bitmap stores a signed distance field rather than the rendered image.
+    sdf: bool,
+    /// Options used to generate the signed distance field, if the sprite generates one.
+    sdf_options: SdfOptions,
+    /// Whether the bitmap has been cropped to remove transparent edges.
+    cropped: bool,
+}
+
+/// The source image of a [`Sprite`].
+#[derive(Clone)]
+enum SpriteSource {
+    /// Parsed source SVG image.
+    Svg(Box<Tree>),
+    /// SVG image rendered by a [`RenderCache`], with the stretchable icon metadata found when it
+    /// was rendered.
+    CachedSvg(Box<CachedSvg>),
+    /// Bitmap image, such as a PNG image or a sprite sliced from an existing spritesheet. Any
+    /// stretchable icon metadata is stored alongside the bitmap, in the bitmap's pixel coordinates.
+    /// If `sdf` is true the bitmap already stores a signed distance field, buffered on each side by
+    /// `sdf_buffer` pixels if that's known.
+    Bitmap {
+        pixmap: Pixmap,
+        pixel_ratio: u8,
+        content: Option<Rect>,
+        stretch_x: Option<Vec<Rect>>,
+        stretch_y: Option<Vec<Rect>>,
+        sdf: bool,
+        sdf_buffer: Option<u32>,
+    },
 }
 
 impl Sprite {
-    pub fn new(tree: Tree, pixel_ratio: u8) -> Option<Self> {
+    /// Create a sprite by rasterising an SVG at `pixel_ratio`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the rasterised image would have no width or height
+    /// (such as when `pixel_ratio` is zero), or would be too large to create.
+    pub fn new(tree: Tree, pixel_ratio: u8) -> SpreetResult<Self> {
         let pixel_ratio_f32 = pixel_ratio.into();
-        let pixmap_size = tree.size().to_int_size().scale_by(pixel_ratio_f32)?;
-        let mut pixmap = Pixmap::new(pixmap_size.width(), pixmap_size.height())?;
+        let size = tree.size().to_int_size();
+        let (width, height) = scale_size(size.width(), size.height(), pixel_ratio_f32);
+        let mut pixmap = new_pixmap(width, height)?;
         let render_ts = Transform::from_scale(pixel_ratio_f32, pixel_ratio_f32);
         resvg::render(&tree, render_ts, &mut pixmap.as_mut());
-        Some(Self {
-            tree,
+        Ok(Self {
+            source: SpriteSource::Svg(Box::new(tree)),
             pixel_ratio,
             pixmap,
             center: None,
+            sdf: false,
+            sdf_options: SdfOptions::default(),
+            cropped: false,
         })
     }
 
@@ -72,7 +130,14 @@ impl Sprite {
     /// Note SDF icons are buffered on each side by `3 * pixel_ratio` pixels. An icon with a ratio
     /// of 1 is buffered by 3px per side, an icon with a ratio of 2 is buffered by 6px per side, and
     /// so on. This makes SDF sprites wider and higher than the original SVG image by
-    /// `6 * pixel_ratio` pixels in total.
+    /// `6 * pixel_ratio` pixels in total. The buffer is recorded in the index file (see
+    /// [`Sprite::sdf_buffer`]). Use [`Sprite::new_sdf_with_options`] to change the buffer, radius
+    /// and cut-off.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the rasterised image would have no width or height,
+    /// or would be too large to create, or if its signed distance field can't be generated.
     ///
     /// # Panics
     ///
@@ -84,71 +149,209 @@ impl Sprite {
     /// [3]: https://blog.demofox.org/2014/06/30/distance-field-textures/
     /// [4]: https://docs.mapbox.com/help/troubleshooting/using-recolorable-images-in-mapbox-maps/
     /// [5]: https://github.com/elastic/fontnik/blob/fcaecc174d7561d9147499ba4f254dc7e1b0feea/lib/sdf.js#L225-L230
-    pub fn new_sdf(tree: Tree, pixel_ratio: u8) -> Option<Self> {
+    pub fn new_sdf(tree: Tree, pixel_ratio: u8) -> SpreetResult<Self> {
+        Self::new_sdf_with_options(tree, pixel_ratio, SdfOptions::default())
+    }
+
+    /// Create a sprite by rasterising an SVG and generating its signed distance field with the
+    /// specified options. See [`Sprite::new_sdf`] for details.
+    pub fn new_sdf_with_options(
+        tree: Tree,
+        pixel_ratio: u8,
+        sdf_options: SdfOptions,
+    ) -> SpreetResult<Self> {
         let pixel_ratio_f32 = pixel_ratio.into();
-        let unbuff_pixmap_size = tree.size().to_int_size().scale_by(pixel_ratio_f32)?;
-        let mut unbuff_pixmap =
-            Pixmap::new(unbuff_pixmap_size.width(), unbuff_pixmap_size.height())?;
+        let size = tree.size().to_int_size();
+        let (width, height) = scale_size(size.width(), size.height(), pixel_ratio_f32);
+        let mut unbuff_pixmap = new_pixmap(width, height)?;
         let render_ts = Transform::from_scale(pixel_ratio_f32, pixel_ratio_f32);
         resvg::render(&tree, render_ts, &mut unbuff_pixmap.as_mut());
 
-        // Scale the buffer by the pixel ratio so the SDF boundary scales with retina sprites. The
-        // Buffer was originally a fixed size of three pixels, as found in
-        // https://github.com/elastic/spritezero/blob/3b89dc0fef2acbf9/index.js#L144. But after
-        // https://github.com/flother/spreet/issues/86 it was deemed that it should be tied to the
-        // pixel ratio.
-        let buffer = 3 * (pixel_ratio as i32);
-        let mut buff_pixmap = Pixmap::new(
-            unbuff_pixmap_size.width() + 2 * buffer as u32,
-            unbuff_pixmap_size.height() + 2 * buffer as u32,
-        )?;
-        buff_pixmap.draw_pixmap(
-            buffer,
-            buffer,
-            unbuff_pixmap.as_ref(),
-            &PixmapPaint::default(),
-            Transform::default(),
-            None,
-        );
-        let alpha = buff_pixmap
-            .pixels()
-            .iter()
-            .map(|pixel| pixel.alpha())
-            .collect::<Vec<u8>>();
-        let bitmap = BitmapGlyph::new(
-            alpha,
-            unbuff_pixmap_size.width() as usize,
-            unbuff_pixmap_size.height() as usize,
-            buffer as usize,
+        Ok(Self {
+            source: SpriteSource::Svg(Box::new(tree)),
+            pixel_ratio,
+            pixmap: sdf_pixmap(&unbuff_pixmap, pixel_ratio, &sdf_options)?,
+            center: None,
+            sdf: true,
+            sdf_options,
+            cropped: false,
+        })
+    }
+
+    /// Create a sprite from a bitmap image, such as a PNG or WebP image loaded with
+    /// [`load_raster`](crate::load_raster).
+    ///
+    /// `source_pixel_ratio` is the pixel ratio the bitmap was drawn at (see
+    /// [`raster_pixel_ratio`](crate::raster_pixel_ratio)). The bitmap is resampled to `pixel_ratio`
+    /// if the two ratios differ, so an image drawn at a pixel ratio of 2 is halved in size for a
+    /// sprite with a pixel ratio of 1. The sprite has no SVG image, and so has no stretchable icon
+    /// metadata.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if either pixel ratio is zero, or if the resampled bitmap
+    /// would have no width or height, or would be too large to create.
+    pub fn from_pixmap(
+        pixmap: Pixmap,
+        source_pixel_ratio: u8,
+        pixel_ratio: u8,
+    ) -> SpreetResult<Self> {
+        Self::from_raster(pixmap, source_pixel_ratio, pixel_ratio, None)
+    }
+
+    /// Create a sprite from a bitmap image, generating its signed distance field from the bitmap's
+    /// alpha channel.
+    ///
+    /// The bitmap is resampled as in [`Sprite::from_pixmap`] and then treated the same way as a
+    /// rendered SVG image in [`Sprite::new_sdf`].
+    pub fn from_pixmap_sdf(
+        pixmap: Pixmap,
+        source_pixel_ratio: u8,
+        pixel_ratio: u8,
+    ) -> SpreetResult<Self> {
+        Self::from_pixmap_sdf_with_options(
+            pixmap,
+            source_pixel_ratio,
+            pixel_ratio,
+            SdfOptions::default(),
         )
-        .ok()?;
-        // Radius and cutoff are recommended to be 8 and 0.25 respectively for a 1x ratio sprite.
-        // https://github.com/stadiamaps/sdf_font_tools/blob/97c5634b8e3515ac7761d0a4f67d12e7f688b042/pbf_font_tools/src/ft_generate.rs#L32-L34
-        // But the radius should scale with the pixel ratio, so that the signed-distance window
-        // remains consistent at higher ratios.
-        let sdf_radius = 8 * (pixel_ratio as usize);
-        let colors = clamp_to_u8(&bitmap.render_sdf(sdf_radius), 0.25)
-            .ok()?
-            .into_iter()
-            .map(|alpha| {
-                Color::from_rgba(0.0, 0.0, 0.0, alpha as f32 / 255.0)
-                    .unwrap()
-                    .premultiply()
-                    .to_color_u8()
-            })
-            .collect::<Vec<_>>();
-        for (i, pixel) in buff_pixmap.pixels_mut().iter_mut().enumerate() {
-            *pixel = colors[i];
-        }
+    }
+
+    /// Create a sprite from a bitmap image, generating its signed distance field with the
+    /// specified options. See [`Sprite::from_pixmap_sdf`] for details.
+    pub fn from_pixmap_sdf_with_options(
+        pixmap: Pixmap,
+        source_pixel_ratio: u8,
+        pixel_ratio: u8,
+        sdf_options: SdfOptions,
+    ) -> SpreetResult<Self> {
+        Self::from_raster(pixmap, source_pixel_ratio, pixel_ratio, Some(sdf_options))
+    }
 
-        Some(Self {
-            tree,
+    fn from_raster(
+        pixmap: Pixmap,
+        source_pixel_ratio: u8,
+        pixel_ratio: u8,
+        sdf_options: Option<SdfOptions>,
+    ) -> SpreetResult<Self> {
+        if source_pixel_ratio == 0 || pixel_ratio == 0 {
+            return Err(SpreetError::PixelRatioError);
+        }
+        let resampled = resample(
+            &pixmap,
+            f32::from(pixel_ratio) / f32::from(source_pixel_ratio),
+        )?;
+        Ok(Self {
+            source: SpriteSource::Bitmap {
+                pixmap,
+                pixel_ratio: source_pixel_ratio,
+                content: None,
+                stretch_x: None,
+                stretch_y: None,
+                sdf: false,
+                sdf_buffer: None,
+            },
             pixel_ratio,
-            pixmap: buff_pixmap,
+            pixmap: match &sdf_options {
+                Some(sdf_options) => sdf_pixmap(&resampled, pixel_ratio, sdf_options)?,
+                None => resampled,
+            },
             center: None,
+            sdf: sdf_options.is_some(),
+            sdf_options: sdf_options.unwrap_or_default(),
+            cropped: false,
         })
     }
 
+    /// Create a sprite from a sprite's description in a spritesheet and its bitmap image, as
+    /// sliced from the spritesheet.
+    ///
+    /// The sprite has no SVG image. Its pixel ratio, stretchable icon metadata, pre-crop center and
+    /// SDF flag are all taken from `description`. See also [`Spritesheet::sprites`].
+    pub fn from_description(description: &SpriteDescription, pixmap: Pixmap) -> Self {
+        Self {
+            source: SpriteSource::Bitmap {
+                pixmap: pixmap.clone(),
+                pixel_ratio: description.pixel_ratio,
+                content: description.content,
+                stretch_x: description.stretch_x.clone(),
+                stretch_y: description.stretch_y.clone(),
+                sdf: description.sdf,
+                sdf_buffer: description.sdf_buffer,
+            },
+            pixel_ratio: description.pixel_ratio,
+            pixmap,
+            center: description.center,
+            sdf: description.sdf,
+            sdf_options: SdfOptions::default(),
+            cropped: false,
+        }
+    }
+
+    /// Create a copy of the sprite rendered at a different pixel ratio.
+    ///
+    /// The sprite's SVG tree is rendered again rather than parsed again (or parsed from its file if
+    /// the sprite was loaded from a [`RenderCache`]), and the new sprite is created the same way as
+    /// the original: as an SDF sprite if the original was created with [`Sprite::new_sdf`], and
+    /// cropped if the original was [cropped](Self::crop). Sprites without an SVG image have their
+    /// bitmap resampled instead.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the new sprite can't be created, for example because
+    /// `pixel_ratio` is zero.
+    pub fn with_pixel_ratio(&self, pixel_ratio: u8) -> SpreetResult<Self> {
+        if pixel_ratio == self.pixel_ratio {
+            return Ok(self.clone());
+        }
+        let mut sprite = match &self.source {
+            SpriteSource::Svg(tree) if self.sdf => {
+                Self::new_sdf_with_options(Tree::clone(tree), pixel_ratio, self.sdf_options)?
+            }
+            SpriteSource::Svg(tree) => Self::new(Tree::clone(tree), pixel_ratio)?,
+            SpriteSource::CachedSvg(svg) if self.sdf => {
+                Self::new_sdf_with_options(svg.load_tree()?, pixel_ratio, self.sdf_options)?
+            }
+            SpriteSource::CachedSvg(svg) => Self::new(svg.load_tree()?, pixel_ratio)?,
+            SpriteSource::Bitmap {
+                pixmap,
+                pixel_ratio: source_ratio,
+                sdf: source_sdf,
+                ..
+            } => {
+                let scale = f32::from(pixel_ratio) / f32::from(*source_ratio);
+                let resampled = resample(pixmap, scale)?;
+                Self {
+                    source: self.source.clone(),
+                    pixel_ratio,
+                    // A signed distance field is generated again unless the source bitmap already
+                    // stores one.
+                    pixmap: if self.sdf && !source_sdf {
+                        sdf_pixmap(&resampled, pixel_ratio, &self.sdf_options)?
+                    } else {
+                        resampled
+                    },
+                    // The center is in this sprite's pixels, which aren't the source bitmap's if
+                    // the sprite has already been resampled.
+                    center: self.center.map(|SpriteCenter { x, y }| {
+                        let scale = f32::from(pixel_ratio) / f32::from(self.pixel_ratio);
+                        SpriteCenter {
+                            x: x * scale,
+                            y: y * scale,
+                        }
+                    }),
+                    sdf: self.sdf,
+                    sdf_options: self.sdf_options,
+                    cropped: false,
+                }
+            }
+        };
+        if self.cropped {
+            sprite.crop(self.center.is_some());
+        }
+        Ok(sprite)
+    }
+
     /// Automatically crop the sprite to remove transparent edges.
     ///
     /// If `record_center` is true, the position of the pre-crop center of the image is recorded.
@@ -201,11 +404,56 @@ impl Sprite {
         cropped.draw_pixmap(0, 0, self.pixmap.as_ref(), &Default::default(), tf, None);
 
         self.pixmap = cropped;
+        self.cropped = true;
     }
 
     /// Get the sprite's SVG tree.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the sprite has no SVG tree (see [`Self::svg_tree`]).
     pub fn tree(&self) -> &Tree {
-        &self.tree
+        self.svg_tree().expect("sprite has no SVG tree")
+    }
+
+    /// Get the sprite's SVG tree, if it was created from an SVG image.
+    ///
+    /// Sprites created from bitmap images, or sliced from a spritesheet, have no tree. Nor do
+    /// sprites that a [`RenderCache`] loaded from its directory, rather than rendered, because
+    /// their SVG image isn't parsed.
+    pub fn svg_tree(&self) -> Option<&Tree> {
+        match &self.source {
+            SpriteSource::Svg(tree) => Some(tree.as_ref()),
+            SpriteSource::CachedSvg(svg) => svg.tree.as_deref(),
+            SpriteSource::Bitmap { .. } => None,
+        }
+    }
+
+    /// Whether the sprite's bitmap stores a signed distance field. See [`Sprite::new_sdf`].
+    pub fn is_sdf(&self) -> bool {
+        self.sdf
+    }
+
+    /// Get the number of pixels by which an SDF sprite's bitmap is buffered on each side.
+    ///
+    /// Returns `None` if the sprite isn't an SDF sprite, or if it was sliced from a spritesheet
+    /// whose index file doesn't record the buffer.
+    pub fn sdf_buffer(&self) -> Option<u32> {
+        if !self.sdf {
+            return None;
+        }
+        match &self.source {
+            SpriteSource::Bitmap {
+                pixel_ratio,
+                sdf: true,
+                sdf_buffer,
+                ..
+            } => sdf_buffer.map(|buffer| {
+                (buffer as f32 * f32::from(self.pixel_ratio) / f32::from(*pixel_ratio)).round()
+                    as u32
+            }),
+            _ => Some(u32::from(self.sdf_options.buffer) * u32::from(self.pixel_ratio)),
+        }
     }
 
     /// Get the sprite's pixel ratio.
@@ -236,7 +484,11 @@ impl Sprite {
     /// [stretchable icon]: https://github.com/mapbox/mapbox-gl-js/issues/8917
     /// [`icon-text-fit`]: https://maplibre.org/maplibre-style-spec/layers/#icon-text-fit
     pub fn content_area(&self) -> Option<Rect> {
-        self.get_node_bbox("mapbox-content")
+        match &self.source {
+            SpriteSource::Svg(_) => self.get_node_bbox("mapbox-content"),
+            SpriteSource::CachedSvg(svg) => svg.content,
+            SpriteSource::Bitmap { content, .. } => self.scale_bitmap_rect(content.as_ref()?),
+        }
     }
 
     /// Metadata for a [stretchable icon].
@@ -251,6 +503,16 @@ impl Sprite {
     ///
     /// [stretchable icon]: https://github.com/mapbox/mapbox-gl-js/issues/8917
     pub fn stretch_x_areas(&self) -> Option<Vec<Rect>> {
+        if let SpriteSource::Bitmap { stretch_x, .. } = &self.source {
+            return stretch_x
+                .as_ref()?
+                .iter()
+                .map(|rect| self.scale_bitmap_rect(rect))
+                .collect();
+        }
+        if let SpriteSource::CachedSvg(svg) = &self.source {
+            return svg.stretch_x.clone();
+        }
         let mut values = vec![];
         // First look for an SVG element with the id `mapbox-stretch-x`.
         if let Some(rect) = self.get_node_bbox("mapbox-stretch-x") {
@@ -287,6 +549,16 @@ impl Sprite {
     ///
     /// [stretchable icon]: https://github.com/mapbox/mapbox-gl-js/issues/8917
     pub fn stretch_y_areas(&self) -> Option<Vec<Rect>> {
+        if let SpriteSource::Bitmap { stretch_y, .. } = &self.source {
+            return stretch_y
+                .as_ref()?
+                .iter()
+                .map(|rect| self.scale_bitmap_rect(rect))
+                .collect();
+        }
+        if let SpriteSource::CachedSvg(svg) = &self.source {
+            return svg.stretch_y.clone();
+        }
         let mut values = vec![];
         // First look for an SVG element with the id `mapbox-stretch-y`.
         if let Some(rect) = self.get_node_bbox("mapbox-stretch-y") {
@@ -314,7 +586,10 @@ impl Sprite {
     /// Find a node in the SVG tree with a given id, and return its bounding box with coordinates
     /// multiplied by the sprite's pixel ratio.
     fn get_node_bbox(&self, id: &str) -> Option<Rect> {
-        let bbox = self.tree.node_by_id(id)?.abs_bounding_box();
+        let SpriteSource::Svg(tree) = &self.source else {
+            return None;
+        };
+        let bbox = tree.node_by_id(id)?.abs_bounding_box();
         let ratio = self.pixel_ratio as f32;
         Rect::from_ltrb(
             bbox.left() * ratio,
@@ -323,45 +598,86 @@ impl Sprite {
             bbox.bottom() * ratio,
         )
     }
+
+    /// Scale a rectangle in the coordinates of the sprite's source bitmap to the sprite's pixel
+    /// ratio.
+    fn scale_bitmap_rect(&self, rect: &Rect) -> Option<Rect> {
+        let SpriteSource::Bitmap { pixel_ratio, .. } = &self.source else {
+            return None;
+        };
+        let scale = f32::from(self.pixel_ratio) / f32::from(*pixel_ratio);
+        Rect::from_ltrb(
+            rect.left() * scale,
+            rect.top() * scale,
+            rect.right() * scale,
+            rect.bottom() * scale,
+        )
+    }
+}
+
+/// Options for generating a sprite's signed distance field. See [`Sprite::new_sdf`].
+///
+/// The buffer and radius are given in pixels for a sprite with a pixel ratio of 1, and are
+/// multiplied by the sprite's pixel ratio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SdfOptions {
+    /// Transparent pixels added to each side of the sprite, so there's room for the distance field
+    /// outside the image's edges.
+    pub buffer: u8,
+    /// Maximum distance from an edge, in pixels, encoded in the distance field.
+    pub radius: u8,
+    /// Proportion of the distance field, between 0 and 1, that lies inside the image's edges.
+    pub cutoff: f64,
 }
 
 /// A description of a sprite image within a spritesheet. Used for the JSON output required by a
 /// Mapbox Style Specification [index file].
 ///
 /// [index file]: https://docs.mapbox.com/mapbox-gl-js/style-spec/sprite/#index-file
-#[derive(Clone, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SpriteDescription {
     pub height: u32,
+    #[serde(default = "default_pixel_ratio")]
     pub pixel_ratio: u8,
     pub width: u32,
     pub x: u32,
     pub y: u32,
     #[serde(
+        default,
         skip_serializing_if = "Option::is_none",
-        serialize_with = "serialize_rect"
+        serialize_with = "serialize_rect",
+        deserialize_with = "deserialize_rect"
     )]
     pub content: Option<Rect>,
     #[serde(
+        default,
         skip_serializing_if = "Option::is_none",
-        serialize_with = "serialize_stretch_x_area"
+        serialize_with = "serialize_stretch_x_area",
+        deserialize_with = "deserialize_stretch_x_area"
     )]
     pub stretch_x: Option<Vec<Rect>>,
     #[serde(
+        default,
         skip_serializing_if = "Option::is_none",
-        serialize_with = "serialize_stretch_y_area"
+        serialize_with = "serialize_stretch_y_area",
+        deserialize_with = "deserialize_stretch_y_area"
     )]
     pub stretch_y: Option<Vec<Rect>>,
-    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub sdf: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sdf_buffer: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub center: Option<SpriteCenter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
 }
 
 /// The center of a sprite before cropping.
 ///
 /// Only included if the sprite is cropped and recording the center was specifically requested.
-#[derive(Clone, Copy, Serialize)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct SpriteCenter {
     pub x: f32,
     pub y: f32,
@@ -378,8 +694,10 @@ impl SpriteDescription {
             content: sprite.content_area(),
             stretch_x: sprite.stretch_x_areas(),
             stretch_y: sprite.stretch_y_areas(),
-            sdf,
+            sdf: sdf || sprite.sdf,
+            sdf_buffer: sprite.sdf_buffer(),
             center: sprite.center,
+            page: None,
         }
     }
 }
@@ -389,18 +707,31 @@ impl SpriteDescription {
 #[derive(Default, Clone)]
 pub struct SpritesheetBuilder {
     sprites: Option<BTreeMap<String, Sprite>>,
-    references: Option<MultiMap<String, String>>,
+    unique: bool,
     spacing: u8,
     sdf: bool,
+    previous: PreviousIndex,
+    max_size: Option<u32>,
+    packer: Option<Arc<dyn Packer + Send + Sync>>,
+    layout: Layout,
+    render_cache: Option<RenderCache>,
 }
 
+/// Sprite descriptions from previous builds, grouped by pixel ratio and then by name.
+type PreviousIndex = BTreeMap<u8, BTreeMap<String, SpriteDescription>>;
+
 impl SpritesheetBuilder {
     pub fn new() -> Self {
         Self {
             sprites: None,
-            references: None,
+            unique: false,
             spacing: 0,
             sdf: false,
+            previous: BTreeMap::new(),
+            max_size: None,
+            packer: None,
+            layout: Layout::default(),
+            render_cache: None,
         }
     }
 
@@ -417,31 +748,10 @@ impl SpritesheetBuilder {
 
     // Remove any duplicate sprites from the spritesheet's sprites. This is used to let spritesheets
     // include only unique sprites, with multiple references to the same sprite in the index file.
+    // Duplicates are found when the spritesheet is generated, so that sprites rendered at different
+    // pixel ratios are compared separately.
     pub fn make_unique(&mut self) -> &mut Self {
-        match self.sprites.take() {
-            Some(sprites) => {
-                let mut unique_sprites = BTreeMap::new();
-                let mut references = MultiMap::new();
-                let mut names_for_sprites: BTreeMap<Vec<u8>, String> = BTreeMap::new();
-                for (name, sprite) in sprites {
-                    let sprite_data = sprite.pixmap().encode_png().unwrap();
-                    match names_for_sprites.entry(sprite_data) {
-                        Entry::Occupied(existing_sprite_name) => {
-                            references.insert(existing_sprite_name.get().clone(), name);
-                        }
-                        Entry::Vacant(entry) => {
-                            entry.insert(name.clone());
-                            unique_sprites.insert(name, sprite);
-                        }
-                    }
-                }
-                self.sprites = Some(unique_sprites);
-                self.references = Some(references);
-            }
-            None => {
-                self.references = None;
-            }
-        }
+        self.unique = true;
         self
     }
 
@@ -454,13 +764,252 @@ impl SpritesheetBuilder {
         self
     }
 
-    pub fn generate(self) -> Option<Spritesheet> {
-        Spritesheet::new(
-            self.sprites.unwrap_or_default(),
-            self.references.unwrap_or_default(),
-            self.spacing,
-            self.sdf,
-        )
+    /// Keep sprites at the positions they had in the index of a previous build.
+    ///
+    /// When the spritesheet is generated, each sprite with the same name, pixel ratio and size as
+    /// an entry in `index` is placed at that entry's coordinates, as long as it doesn't overlap
+    /// another sprite kept in place. Only new, resized or displaced sprites are packed, into the
+    /// free space around the kept sprites. This keeps spritesheets stable across rebuilds, so
+    /// changes are easier to review and cached images stay valid for longer.
+    ///
+    /// This can be called more than once, for example with the previous index for each pixel ratio
+    /// passed to [`Self::generate_ratios`].
+    pub fn previous_index(&mut self, index: BTreeMap<String, SpriteDescription>) -> &mut Self {
+        for (name, description) in index {
+            self.previous
+                .entry(description.pixel_ratio)
+                .or_default()
+                .insert(name, description);
+        }
+        self
+    }
+
+    /// Limit the width and height of the spritesheet to `max_size` pixels.
+    ///
+    /// Use [`Self::generate_pages`] to split sprites that don't fit within the limit across several
+    /// spritesheets (pages). Devices limit the size of the textures they can display, often to 4096
+    /// or 8192 pixels, so large sets of sprites may need more than one page.
+    pub fn max_size(&mut self, max_size: u32) -> &mut Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Use `packer` to arrange the sprites in the spritesheet, instead of the default
+    /// [`CrunchPacker`].
+    pub fn packer<P: Packer + Send + Sync + 'static>(&mut self, packer: P) -> &mut Self {
+        self.packer = Some(Arc::new(packer));
+        self
+    }
+
+    /// Allow the spritesheet to have any width and height, instead of only powers of two.
+    ///
+    /// Several widths are tried, and the one that the sprites fill most tightly is used.
+    pub fn allow_any_size(&mut self) -> &mut Self {
+        self.layout.any_size = true;
+        self
+    }
+
+    /// Make the spritesheet square, padding it with transparent pixels if needed.
+    pub fn make_square(&mut self) -> &mut Self {
+        self.layout.square = true;
+        self
+    }
+
+    /// Make the spritesheet exactly `width` pixels wide, and only as high as the sprites need.
+    ///
+    /// The width can't be more than the [maximum size](Self::max_size).
+    pub fn fixed_width(&mut self, width: u32) -> &mut Self {
+        self.layout.width = Some(width);
+        self
+    }
+
+    /// Load sprites rendered at other pixel ratios by [`Self::generate_ratios`] from `cache`, and
+    /// store newly rendered sprites in it. See [`RenderCache::with_pixel_ratio`].
+    pub fn render_cache(&mut self, cache: RenderCache) -> &mut Self {
+        self.render_cache = Some(cache);
+        self
+    }
+
+    /// Generate the spritesheet.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there are no sprites, if a sprite can't be rendered,
+    /// or if the sprites can't be packed, including when they don't fit within the
+    /// [maximum size](Self::max_size) of a single spritesheet.
+    pub fn generate(self) -> SpreetResult<Spritesheet> {
+        let max_size = self.max_size;
+        single_page(self.generate_pages()?, max_size)
+    }
+
+    /// Generate one spritesheet for each of the given pixel ratios.
+    ///
+    /// Each sprite is rendered again at every ratio using [`Sprite::with_pixel_ratio`], so the
+    /// source SVGs only need to be parsed once. The spritesheets are returned in a map keyed by
+    /// pixel ratio; use [`ratio_file_prefix`] to name the files they're saved to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the same cases as [`Self::generate`].
+    pub fn generate_ratios(self, ratios: &[u8]) -> SpreetResult<BTreeMap<u8, Spritesheet>> {
+        let max_size = self.max_size;
+        self.generate_ratio_pages(ratios)?
+            .into_iter()
+            .map(|(ratio, pages)| Ok((ratio, single_page(pages, max_size)?)))
+            .collect()
+    }
+
+    /// Generate as many spritesheets (pages) as are needed to fit the sprites within the
+    /// [maximum size](Self::max_size).
+    ///
+    /// Each page has its own index. Use [`page_file_prefix`] to name the files they're saved to,
+    /// and [`Spritesheet::combined_index`] to create a single index for all the pages. Without a
+    /// maximum size, there's always exactly one page.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there are no sprites, or if the sprites can't be
+    /// packed, such as when a sprite is larger than the maximum size.
+    pub fn generate_pages(mut self) -> SpreetResult<Vec<Spritesheet>> {
+        let sprites = self.sprites.take().unwrap_or_default();
+        self.generate_from(sprites)
+    }
+
+    /// Generate the pages of a spritesheet for each of the given pixel ratios. See
+    /// [`Self::generate_ratios`] and [`Self::generate_pages`].
+    ///
+    /// The sprites are split into pages at the largest ratio, where they take up the most room, and
+    /// each page has the same sprites at every other ratio. This means that the files for a page at
+    /// each ratio can be used together as one sprite in a map style.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there are no sprites, if a sprite can't be rendered
+    /// at one of the ratios, or if the sprites can't be packed.
+    pub fn generate_ratio_pages(
+        mut self,
+        ratios: &[u8],
+    ) -> SpreetResult<BTreeMap<u8, Vec<Spritesheet>>> {
+        let sprites = Vec::from_iter(self.sprites.take().unwrap_or_default());
+        let Some(&largest_ratio) = ratios.iter().max() else {
+            return Ok(BTreeMap::new());
+        };
+        let largest_pages = self.generate_from(self.sprites_at_ratio(&sprites, largest_ratio)?)?;
+        let page_names = largest_pages
+            .iter()
+            .map(|page| page.index.keys().cloned().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let mut ratio_pages = BTreeMap::from([(largest_ratio, largest_pages)]);
+        for &ratio in ratios {
+            if ratio_pages.contains_key(&ratio) {
+                continue;
+            }
+            let mut ratio_sprites = self.sprites_at_ratio(&sprites, ratio)?;
+            let pages = page_names
+                .iter()
+                .map(|names| {
+                    let page_sprites = names
+                        .iter()
+                        .filter_map(|name| ratio_sprites.remove_entry(name))
+                        .collect();
+                    single_page(self.generate_from(page_sprites)?, self.max_size)
+                })
+                .collect::<SpreetResult<Vec<_>>>()?;
+            ratio_pages.insert(ratio, pages);
+        }
+        Ok(ratio_pages)
+    }
+
+    /// Render `sprites` again at `pixel_ratio`, using the render cache if there is one.
+    fn sprites_at_ratio(
+        &self,
+        sprites: &[(String, Sprite)],
+        pixel_ratio: u8,
+    ) -> SpreetResult<BTreeMap<String, Sprite>> {
+        par_map(sprites, |(name, sprite)| {
+            let sprite = match &self.render_cache {
+                Some(cache) => cache.with_pixel_ratio(sprite, pixel_ratio)?,
+                None => sprite.with_pixel_ratio(pixel_ratio)?,
+            };
+            Ok((name.clone(), sprite))
+        })
+        .into_iter()
+        .collect()
+    }
+
+    fn generate_from(&self, sprites: BTreeMap<String, Sprite>) -> SpreetResult<Vec<Spritesheet>> {
+        let (sprites, references) = if self.unique {
+            unique_sprites(sprites)
+        } else {
+            (sprites, MultiMap::new())
+        };
+        Spritesheet::new_pages(sprites, references, self)
+    }
+}
+
+/// The only page of a spritesheet, or a packing error if the sprites needed more than one page of
+/// `max_size`.
+fn single_page(mut pages: Vec<Spritesheet>, max_size: Option<u32>) -> SpreetResult<Spritesheet> {
+    match (pages.pop(), pages.is_empty()) {
+        (Some(page), true) => Ok(page),
+        _ => {
+            let max_size = max_size.unwrap_or_default();
+            Err(SpreetError::PackingError {
+                width: max_size,
+                height: max_size,
+            })
+        }
+    }
+}
+
+/// Split `sprites` into the sprites with unique bitmaps and a map from the name of each unique
+/// sprite to the names of its duplicates.
+fn unique_sprites(
+    sprites: BTreeMap<String, Sprite>,
+) -> (BTreeMap<String, Sprite>, MultiMap<String, String>) {
+    let mut unique_sprites = BTreeMap::<String, Sprite>::new();
+    let mut references = MultiMap::new();
+    let mut names_for_hashes: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+    let sprites = Vec::from_iter(sprites);
+    let hashes = par_map(&sprites, |(_, sprite)| pixmap_hash(sprite.pixmap()));
+    for ((name, sprite), hash) in sprites.into_iter().zip(hashes) {
+        // Sprites with the same hash are compared pixel by pixel, in case two different bitmaps
+        // have the same hash.
+        let names = names_for_hashes.entry(hash).or_default();
+        let existing_sprite_name = names
+            .iter()
+            .find(|existing| unique_sprites[*existing].pixmap() == sprite.pixmap());
+        match existing_sprite_name {
+            Some(existing_sprite_name) => {
+                references.insert(existing_sprite_name.clone(), name);
+            }
+            None => {
+                names.push(name.clone());
+                unique_sprites.insert(name, sprite);
+            }
+        }
+    }
+    (unique_sprites, references)
+}
+
+/// A hash of a bitmap's width, height and pixels.
+fn pixmap_hash(pixmap: &Pixmap) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (pixmap.width(), pixmap.height(), pixmap.data()).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Apply `f` to each of `items`, returning the results in the same order as the items. With the
+/// `parallel` feature, the items are processed on several threads.
+pub(crate) fn par_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Send + Sync) -> Vec<R> {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        items.par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.iter().map(f).collect()
     }
 }
 
@@ -475,44 +1024,338 @@ struct PixmapItem {
     sprite: Sprite,
 }
 
+/// Resample a bitmap image by `scale`, e.g. a scale of 2 doubles its width and height.
+fn resample(pixmap: &Pixmap, scale: f32) -> SpreetResult<Pixmap> {
+    if scale == 1.0 {
+        return Ok(pixmap.clone());
+    }
+    let (width, height) = scale_size(pixmap.width(), pixmap.height(), scale);
+    let mut resampled = new_pixmap(width, height)?;
+    let paint = PixmapPaint {
+        quality: FilterQuality::Bicubic,
+        ..PixmapPaint::default()
+    };
+    resampled.draw_pixmap(
+        0,
+        0,
+        pixmap.as_ref(),
+        &paint,
+        Transform::from_scale(scale, scale),
+        None,
+    );
+    Ok(resampled)
+}
+
+/// The width and height of an image scaled by `scale`, rounded to whole pixels in the same way as
+/// [`IntSize::scale_by`](resvg::tiny_skia::IntSize::scale_by).
+fn scale_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+    (
+        (width as f32 * scale).round() as u32,
+        (height as f32 * scale).round() as u32,
+    )
+}
+
+/// Create an empty bitmap image, or an error explaining why one of that size can't be created.
+fn new_pixmap(width: u32, height: u32) -> SpreetResult<Pixmap> {
+    if width == 0 || height == 0 {
+        return Err(SpreetError::ZeroSizedImageError);
+    }
+    Pixmap::new(width, height).ok_or(SpreetError::ImageTooLargeError { width, height })
+}
+
+/// Generate the signed distance field of a bitmap image, and store it in the alpha channel of a
+/// new bitmap that's buffered on each side. See [`Sprite::new_sdf`] for details.
+fn sdf_pixmap(
+    unbuff_pixmap: &Pixmap,
+    pixel_ratio: u8,
+    options: &SdfOptions,
+) -> SpreetResult<Pixmap> {
+    // Scale the buffer by the pixel ratio so the SDF boundary scales with retina sprites. The
+    // Buffer was originally a fixed size of three pixels, as found in
+    // https://github.com/elastic/spritezero/blob/3b89dc0fef2acbf9/index.js#L144. But after
+    // https://github.com/flother/spreet/issues/86 it was deemed that it should be tied to the
+    // pixel ratio.
+    let buffer = i32::from(options.buffer) * i32::from(pixel_ratio);
+    let mut buff_pixmap = new_pixmap(
+        unbuff_pixmap.width() + 2 * buffer as u32,
+        unbuff_pixmap.height() + 2 * buffer as u32,
+    )?;
+    buff_pixmap.draw_pixmap(
+        buffer,
+        buffer,
+        unbuff_pixmap.as_ref(),
+        &PixmapPaint::default(),
+        Transform::default(),
+        None,
+    );
+    let alpha = buff_pixmap
+        .pixels()
+        .iter()
+        .map(|pixel| pixel.alpha())
+        .collect::<Vec<u8>>();
+    let bitmap = BitmapGlyph::new(
+        alpha,
+        unbuff_pixmap.width() as usize,
+        unbuff_pixmap.height() as usize,
+        buffer as usize,
+    )?;
+    // Radius and cutoff are recommended to be 8 and 0.25 respectively for a 1x ratio sprite.
+    // https://github.com/stadiamaps/sdf_font_tools/blob/97c5634b8e3515ac7761d0a4f67d12e7f688b042/pbf_font_tools/src/ft_generate.rs#L32-L34
+    // But the radius should scale with the pixel ratio, so that the signed-distance window
+    // remains consistent at higher ratios.
+    let sdf_radius = usize::from(options.radius) * usize::from(pixel_ratio);
+    let colors = clamp_to_u8(&bitmap.render_sdf(sdf_radius), options.cutoff)?
+        .into_iter()
+        .map(|alpha| {
+            Color::from_rgba(0.0, 0.0, 0.0, alpha as f32 / 255.0)
+                .unwrap()
+                .premultiply()
+                .to_color_u8()
+        })
+        .collect::<Vec<_>>();
+    for (i, pixel) in buff_pixmap.pixels_mut().iter_mut().enumerate() {
+        *pixel = colors[i];
+    }
+
+    Ok(buff_pixmap)
+}
+
+/// Pack sprites into a spritesheet, keeping them at their positions in a previous build's index
+/// where possible.
+///
+/// Sprites keep their previous position if they have the same size as before and don't overlap
+/// another kept sprite. The remaining sprites are placed, tallest first, at the top-most and then
+/// left-most free position next to an already-placed sprite, without making the spritesheet wider
+/// than the kept sprites (unless a sprite is wider than that on its own).
+///
+/// Returns `None` if no sprite can be kept in place, in which case the sprites should be packed
+/// from scratch.
+fn pack_incrementally<'a>(
+    items: &'a [PixmapItem],
+    spacing: u8,
+    previous: &PreviousIndex,
+) -> Option<Vec<PackedItem<&'a PixmapItem>>> {
+    let spacing = spacing as usize;
+    let size = |item: &PixmapItem| {
+        (
+            item.sprite.pixmap.width() as usize + spacing,
+            item.sprite.pixmap.height() as usize + spacing,
+        )
+    };
+
+    let mut packed: Vec<PackedItem<&PixmapItem>> = Vec::new();
+    let mut new_items = Vec::new();
+    for item in items {
+        let (w, h) = size(item);
+        let kept_rect = previous
+            .get(&item.sprite.pixel_ratio)
+            .and_then(|index| index.get(&item.name))
+            .filter(|desc| {
+                desc.width as usize + spacing == w && desc.height as usize + spacing == h
+            })
+            .map(|desc| crunch::Rect::new(desc.x as usize, desc.y as usize, w, h))
+            .filter(|rect| !packed.iter().any(|p| p.rect.overlaps(rect)));
+        match kept_rect {
+            Some(rect) => packed.push(PackedItem { data: item, rect }),
+            None => new_items.push(item),
+        }
+    }
+    if packed.is_empty() {
+        return None;
+    }
+
+    new_items.sort_by_key(|item| {
+        let (w, h) = size(item);
+        (std::cmp::Reverse(h), std::cmp::Reverse(w), &item.name)
+    });
+    let max_width = packed
+        .iter()
+        .map(|p| p.rect.right())
+        .chain(new_items.iter().map(|item| size(item).0))
+        .max()?;
+    for item in new_items {
+        let (w, h) = size(item);
+        // Candidate positions are the top-left corner and the corners just to the right of and just
+        // below each placed sprite. The bottom-left corner of the spritesheet is always free.
+        let mut candidates = vec![(0, 0)];
+        for PackedItem { rect, .. } in &packed {
+            candidates.extend([
+                (rect.right(), rect.y),
+                (rect.x, rect.bottom()),
+                (0, rect.bottom()),
+            ]);
+        }
+        candidates.sort_by_key(|&(x, y)| (y, x));
+        let rect = candidates
+            .into_iter()
+            .map(|(x, y)| crunch::Rect::new(x, y, w, h))
+            .find(|rect| {
+                rect.right() <= max_width && !packed.iter().any(|p| p.rect.overlaps(rect))
+            })?;
+        packed.push(PackedItem { data: item, rect });
+    }
+    Some(packed)
+}
+
+/// Pack sprites into one or more spritesheets (pages), none of which is wider or higher than the
+/// builder's maximum size.
+///
+/// Sprites are kept at their positions in a previous build's index where possible (see
+/// [`pack_incrementally`]), as long as that fits them all on one page. Otherwise, each page is
+/// filled by the builder's packer with as many of the remaining sprites as fit.
+///
+/// Returns a [`PackingError`](SpreetError::PackingError) if the sprites can't be packed, such as
+/// when a sprite is larger than the maximum size.
+fn pack_pages<'a>(
+    items: &'a [PixmapItem],
+    options: &SpritesheetBuilder,
+) -> SpreetResult<Vec<Vec<PackedItem<&'a PixmapItem>>>> {
+    let spacing = options.spacing as u32;
+    let layout = &options.layout;
+    // Spacing is trimmed from the right and bottom edges of the spritesheet, so the packed sprites
+    // can take up that much more room.
+    let max_bin_size = options.max_size.map(|max_size| max_size + spacing);
+    let max_bin_width = layout.width.map(|width| width + spacing).or(max_bin_size);
+    if let Some(items) = pack_incrementally(items, options.spacing, &options.previous) {
+        let fits = |PackedItem { rect, .. }: &PackedItem<&PixmapItem>| {
+            max_bin_width.map_or(true, |max| rect.right() <= max as usize)
+                && max_bin_size.map_or(true, |max| rect.bottom() <= max as usize)
+        };
+        if items.iter().all(fits) {
+            return Ok(vec![items]);
+        }
+    }
+
+    let packer: &dyn Packer = match &options.packer {
+        Some(packer) => packer.as_ref(),
+        None => &CrunchPacker,
+    };
+    let mut remaining = items.iter().collect::<Vec<_>>();
+    let sizes = |items: &[&PixmapItem]| {
+        items
+            .iter()
+            .map(|data| {
+                (
+                    data.sprite.pixmap.width() + spacing,
+                    data.sprite.pixmap.height() + spacing,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let packed_items = |items: &[&'a PixmapItem], positions: Vec<Option<(u32, u32)>>| {
+        items
+            .iter()
+            .zip(sizes(items))
+            .zip(positions)
+            .filter_map(|((&data, (w, h)), position)| {
+                let (x, y) = position?;
+                let rect = crunch::Rect::new(x as usize, y as usize, w as usize, h as usize);
+                Some(PackedItem { data, rect })
+            })
+            .collect::<Vec<_>>()
+    };
+    let Some(max_bin_size) = max_bin_size else {
+        // Minimum area required for the spritesheet (i.e. 100% coverage).
+        let min_area = items
+            .iter()
+            .map(|data| data.sprite.pixmap.width() as usize * data.sprite.pixmap.height() as usize)
+            .sum::<usize>();
+        let max_side = u32::try_from(min_area.saturating_mul(10)).unwrap_or(u32::MAX);
+        let positions = pack_bin(packer, &sizes(&remaining), layout, spacing, max_side).ok_or(
+            SpreetError::PackingError {
+                width: layout.width.unwrap_or(max_side),
+                height: max_side,
+            },
+        )?;
+        return Ok(vec![packed_items(
+            &remaining,
+            positions.into_iter().map(Some).collect(),
+        )]);
+    };
+    let max_bin_width = max_bin_width.unwrap_or(max_bin_size);
+    let packing_error = SpreetError::PackingError {
+        width: max_bin_width - spacing,
+        height: max_bin_size - spacing,
+    };
+    if max_bin_width > max_bin_size {
+        return Err(packing_error);
+    }
+
+    let mut pages = Vec::new();
+    while !remaining.is_empty() {
+        // Pack the remaining sprites as tightly as possible if they all fit on one page, and
+        // otherwise fill a page with as many of them as fit.
+        let remaining_sizes = sizes(&remaining);
+        if let Some(positions) = pack_bin(packer, &remaining_sizes, layout, spacing, max_bin_size) {
+            pages.push(packed_items(
+                &remaining,
+                positions.into_iter().map(Some).collect(),
+            ));
+            break;
+        }
+        let positions = packer.pack(&remaining_sizes, max_bin_width, max_bin_size);
+        let packed = packed_items(&remaining, positions);
+        if packed.is_empty() {
+            return Err(packing_error);
+        }
+        remaining.retain(|&item| !packed.iter().any(|p| std::ptr::eq(p.data, item)));
+        pages.push(packed);
+    }
+    Ok(pages)
+}
+
 /// Optimization level for PNG image output.
+#[derive(Clone, Copy)]
 pub enum Optlevel {
     Oxipng { level: u8 },
     Zopfli { iterations: NonZero<u8> },
 }
 
 impl Spritesheet {
+    /// Pack sprites into a new spritesheet.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there are no sprites, or if they can't be packed.
     pub fn new(
         sprites: BTreeMap<String, Sprite>,
         references: MultiMap<String, String>,
         spacing: u8,
         sdf: bool,
-    ) -> Option<Self> {
-        let mut data_items = Vec::new();
-        let mut min_area: usize = 0;
-
-        // The items are the rectangles that we want to pack into the smallest space possible. We
-        // don't need to pass the pixels themselves, just the unique name for each sprite.
-        for (name, sprite) in sprites {
-            // Minimum area required for the spritesheet (i.e. 100% coverage).
-            min_area += (sprite.pixmap().width() * sprite.pixmap().height()) as usize;
-            data_items.push(PixmapItem { name, sprite });
+    ) -> SpreetResult<Self> {
+        let mut options = SpritesheetBuilder::new();
+        options.spacing(spacing);
+        if sdf {
+            options.make_sdf();
         }
+        single_page(Self::new_pages(sprites, references, &options)?, None)
+    }
 
-        let items = data_items
-            .iter()
-            .map(|data| {
-                Item::new(
-                    data,
-                    data.sprite.pixmap.width() as usize + spacing as usize,
-                    data.sprite.pixmap.height() as usize + spacing as usize,
-                    Rotation::None,
-                )
-            })
+    fn new_pages(
+        sprites: BTreeMap<String, Sprite>,
+        references: MultiMap<String, String>,
+        options: &SpritesheetBuilder,
+    ) -> SpreetResult<Vec<Self>> {
+        if sprites.is_empty() {
+            return Err(SpreetError::EmptySpritesheetError);
+        }
+        let data_items = sprites
+            .into_iter()
+            .map(|(name, sprite)| PixmapItem { name, sprite })
             .collect::<Vec<_>>();
+        pack_pages(&data_items, options)?
+            .into_iter()
+            .map(|items| Self::from_packed(items, &references, options))
+            .collect()
+    }
 
-        let PackedItems { items, .. } = crunch::pack_into_po2(min_area * 10, items).ok()?;
-
+    /// Draw packed sprites into a spritesheet and create its index.
+    fn from_packed(
+        items: Vec<PackedItem<&PixmapItem>>,
+        references: &MultiMap<String, String>,
+        options: &SpritesheetBuilder,
+    ) -> SpreetResult<Self> {
+        let (spacing, sdf) = (options.spacing, options.sdf);
         // There might be some unused space in the packed items --- not all the pixels on
         // the right/bottom edges may have been used. Count the pixels in use so we can
         // strip off any empty edges in the final spritesheet. The won't strip any
@@ -520,22 +1363,32 @@ impl Spritesheet {
         let bin_width = items
             .iter()
             .map(|PackedItem { rect, .. }| rect.right())
-            .max()? as u32;
+            .max()
+            .ok_or(SpreetError::EmptySpritesheetError)? as u32;
         let bin_height = items
             .iter()
             .map(|PackedItem { rect, .. }| rect.bottom())
-            .max()? as u32;
+            .max()
+            .ok_or(SpreetError::EmptySpritesheetError)? as u32;
 
         // Final width and height of the spreadsheet will be trimmed of any spacing added to the
         // right and bottom edges.
-        let final_width = bin_width.saturating_sub(spacing as u32);
-        let final_height = bin_height.saturating_sub(spacing as u32);
+        let mut final_width = bin_width.saturating_sub(spacing as u32);
+        let mut final_height = bin_height.saturating_sub(spacing as u32);
+        // A fixed width or square spritesheet is padded with transparent pixels.
+        if let Some(width) = options.layout.width {
+            final_width = width;
+        }
+        if options.layout.square {
+            final_width = final_width.max(final_height);
+            final_height = final_width;
+        }
 
         // This is the meat of Spreet. Here we pack the sprite bitmaps into the spritesheet,
         // using the rectangle locations from the previous step, and store those locations
         // in the vector that will be output as the sprite index file.
         let mut index = BTreeMap::new();
-        let mut sheet = Pixmap::new(final_width, final_height)?;
+        let mut sheet = new_pixmap(final_width, final_height)?;
         let pixmap_paint = PixmapPaint::default();
         let pixmap_transform = Transform::default();
         for PackedItem { rect, data } in items {
@@ -572,13 +1425,90 @@ impl Spritesheet {
             }
         }
 
-        Some(Spritesheet { sheet, index })
+        Ok(Spritesheet { sheet, index })
     }
 
     pub fn build() -> SpritesheetBuilder {
         SpritesheetBuilder::new()
     }
 
+    /// Load a spritesheet and its index from a PNG image and a JSON index file.
+    ///
+    /// The files can be created by Spreet or by any other tool that produces spritesheets in the
+    /// format described by the Mapbox Style Specification, such as spritezero. See
+    /// [`Self::decode`] for details.
+    pub fn load<P1: AsRef<Path>, P2: AsRef<Path>>(
+        png_path: P1,
+        index_path: P2,
+    ) -> SpreetResult<Self> {
+        Self::decode(&std::fs::read(png_path)?, &std::fs::read(index_path)?)
+    }
+
+    /// Decode a spritesheet and its index from an in-memory PNG image and JSON index.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///
+    /// - the PNG image can't be decoded
+    /// - the index isn't valid JSON or doesn't describe sprites
+    /// - a sprite in the index lies (partly) outside the PNG image
+    pub fn decode(png_data: &[u8], index_data: &[u8]) -> SpreetResult<Self> {
+        let sheet = Pixmap::decode_png(png_data)?;
+        let index: BTreeMap<String, SpriteDescription> = serde_json::from_slice(index_data)?;
+        for (name, description) in &index {
+            let right = description.x.checked_add(description.width);
+            let bottom = description.y.checked_add(description.height);
+            if right.map_or(true, |right| right > sheet.width())
+                || bottom.map_or(true, |bottom| bottom > sheet.height())
+            {
+                return Err(SpreetError::SpriteBoundsError(name.clone()));
+            }
+        }
+        Ok(Self { sheet, index })
+    }
+
+    /// Get the bitmap image of the spritesheet.
+    pub fn pixmap(&self) -> &Pixmap {
+        &self.sheet
+    }
+
+    /// Slice the spritesheet into its sprites.
+    ///
+    /// Each sprite's bitmap is cropped from the spritesheet, and its metadata is taken from the
+    /// index (see [`Sprite::from_description`]). The sprites can be added to a new spritesheet,
+    /// for example to merge several spritesheets into one. Sprites that share an image under
+    /// several names are returned once for each name.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if any of the sprites has no width or height.
+    pub fn sprites(&self) -> SpreetResult<BTreeMap<String, Sprite>> {
+        self.index
+            .iter()
+            .map(|(name, description)| {
+                let pixmap = self
+                    .sprite_pixmap(name)
+                    .ok_or(SpreetError::ZeroSizedImageError)?;
+                Ok((name.clone(), Sprite::from_description(description, pixmap)))
+            })
+            .collect()
+    }
+
+    /// Get a copy of the bitmap image of the sprite named `name`, cropped from the spritesheet.
+    ///
+    /// Returns `None` if there's no sprite with that name in the index, or if the sprite is empty.
+    pub fn sprite_pixmap(&self, name: &str) -> Option<Pixmap> {
+        let description = self.index.get(name)?;
+        let rect = IntRect::from_xywh(
+            description.x.try_into().ok()?,
+            description.y.try_into().ok()?,
+            description.width,
+            description.height,
+        )?;
+        self.sheet.clone_rect(rect)
+    }
+
     /// Encode the spritesheet to the in-memory PNG image.
     ///
     /// The `spritesheet` `Pixmap` is converted to an in-memory PNG, optimised using the [`oxipng`]
@@ -621,12 +1551,13 @@ impl Spritesheet {
     /// in-memory PNG, optimised using the [`oxipng`] library with the default optimization level,
     /// and saved to a local file.
     ///
-    /// The spritesheet will match an index file that can be saved with [`Self::save_index`].
+    /// The spritesheet will match an index file that can be saved with [`Self::save_index`]. An
+    /// existing file is replaced atomically (see [`write_atomically`]).
     ///
     /// [image file]: https://docs.mapbox.com/mapbox-gl-js/style-spec/sprite/#image-file
     /// [`oxipng`]: https://github.com/shssoichiro/oxipng
     pub fn save_spritesheet<P: AsRef<Path>>(&self, path: P) -> SpreetResult<()> {
-        Ok(std::fs::write(path, self.encode_png()?)?)
+        Ok(write_atomically(path, &self.encode_png()?)?)
     }
 
     /// Saves the spritesheet to a local file named `path` with the specified optimization level.
@@ -635,7 +1566,8 @@ impl Spritesheet {
     /// containing all the individual sprite images. The `spritesheet` `Pixmap` is converted to an
     /// in-memory PNG, optimised using the [`oxipng`] library, and saved to a local file.
     ///
-    /// The spritesheet will match an index file that can be saved with [`Self::save_index`].
+    /// The spritesheet will match an index file that can be saved with [`Self::save_index`]. An
+    /// existing file is replaced atomically (see [`write_atomically`]).
     ///
     /// [image file]: https://docs.mapbox.com/mapbox-gl-js/style-spec/sprite/#image-file
     /// [`oxipng`]: https://github.com/shssoichiro/oxipng
@@ -644,7 +1576,7 @@ impl Spritesheet {
         path: P,
         optlevel: Optlevel,
     ) -> SpreetResult<()> {
-        Ok(std::fs::write(path, self.encode_png_at(optlevel)?)?)
+        Ok(write_atomically(path, &self.encode_png_at(optlevel)?)?)
     }
 
     /// Get the `sprite_index` that can be serialized to JSON.
@@ -660,6 +1592,27 @@ impl Spritesheet {
         &self.index
     }
 
+    /// The proportion of the spritesheet's area that's covered by sprites, from 0 to 1.
+    ///
+    /// Sprites that share a bitmap with another sprite (see [`SpritesheetBuilder::make_unique`])
+    /// are only counted once.
+    pub fn fill_ratio(&self) -> f32 {
+        let sheet_area = self.sheet.width() as f32 * self.sheet.height() as f32;
+        let sprite_area = self
+            .index
+            .values()
+            .map(|desc| (desc.x, desc.y, desc.width, desc.height))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|(_, _, width, height)| width as f32 * height as f32)
+            .sum::<f32>();
+        if sheet_area > 0.0 {
+            sprite_area / sheet_area
+        } else {
+            0.0
+        }
+    }
+
     /// Saves the `sprite_index` to a local file named `file_name_prefix` + ".json".
     ///
     /// An [index file] is defined in the Mapbox Style Specification as a JSON document containing a
@@ -667,17 +1620,43 @@ impl Spritesheet {
     /// positions, and pixel ratio of the sprite.
     ///
     /// The index file will match a spritesheet that can be saved with [`Self::save_spritesheet`].
+    /// An existing file is replaced atomically (see [`write_atomically`]).
     ///
     /// [index file]: https://docs.mapbox.com/mapbox-gl-js/style-spec/sprite/#index-file
     pub fn save_index(&self, file_name_prefix: &str, minify: bool) -> std::io::Result<()> {
-        let mut file = File::create(format!("{file_name_prefix}.json"))?;
-        let json_string = if minify {
-            serde_json::to_string(&self.get_index())?
-        } else {
-            serde_json::to_string_pretty(&self.get_index())?
-        };
-        write!(file, "{json_string}")?;
-        Ok(())
+        write_index(self.get_index(), file_name_prefix, minify)
+    }
+
+    /// Combine the indexes of the pages of a spritesheet into a single index.
+    ///
+    /// Each sprite's description records the number of the page it's on, counting from zero, in
+    /// the order the pages are given (see [`SpritesheetBuilder::generate_pages`]).
+    pub fn combined_index(pages: &[Spritesheet]) -> BTreeMap<String, SpriteDescription> {
+        pages
+            .iter()
+            .enumerate()
+            .flat_map(|(page, spritesheet)| {
+                spritesheet.index.iter().map(move |(name, description)| {
+                    let description = SpriteDescription {
+                        page: Some(page),
+                        ..description.clone()
+                    };
+                    (name.clone(), description)
+                })
+            })
+            .collect()
+    }
+
+    /// Saves the [combined index](Self::combined_index) of the pages of a spritesheet to a local
+    /// file named `file_name_prefix` + ".json".
+    ///
+    /// An existing file is replaced atomically (see [`write_atomically`]).
+    pub fn save_combined_index(
+        pages: &[Spritesheet],
+        file_name_prefix: &str,
+        minify: bool,
+    ) -> std::io::Result<()> {
+        write_index(&Self::combined_index(pages), file_name_prefix, minify)
     }
 
     /// Saves the `sprite_index` to a local file named `file_name_prefix` + ".json".
@@ -686,6 +1665,7 @@ impl Spritesheet {
     /// spritesheet. It contains the width, height, X coordinate and Y coordinate of the sprite.
     ///
     /// The index file will match a spritesheet that can be saved with [`Self::save_spritesheet`].
+    /// An existing file is replaced atomically (see [`write_atomically`]).
     pub fn save_index_simple(&self, file_name_prefix: &str, minify: bool) -> std::io::Result<()> {
         #[derive(Serialize)]
         struct SimpleSpriteDescription {
@@ -713,27 +1693,66 @@ impl Spritesheet {
             })
             .collect::<BTreeMap<_, _>>();
 
-        let mut file = File::create(format!("{file_name_prefix}.json"))?;
-        let json_string = if minify {
-            serde_json::to_string(&index)?
-        } else {
-            serde_json::to_string_pretty(&index)?
-        };
-        write!(file, "{json_string}")?;
-        Ok(())
+        write_index(&index, file_name_prefix, minify)
     }
 }
 
+/// Saves an index to a local file named `file_name_prefix` + ".json".
+fn write_index<T: Serialize>(
+    index: &T,
+    file_name_prefix: &str,
+    minify: bool,
+) -> std::io::Result<()> {
+    let json_string = if minify {
+        serde_json::to_string(index)?
+    } else {
+        serde_json::to_string_pretty(index)?
+    };
+    write_atomically(format!("{file_name_prefix}.json"), json_string.as_bytes())
+}
+
 impl Default for Optlevel {
     fn default() -> Self {
         Optlevel::Oxipng { level: 2 }
     }
 }
 
+impl Default for SdfOptions {
+    fn default() -> Self {
+        SdfOptions {
+            buffer: 3,
+            radius: 8,
+            cutoff: 0.25,
+        }
+    }
+}
+
+/// Returns the file name prefix for a spritesheet with the given pixel ratio.
+///
+/// Follows the MapLibre/Mapbox convention of adding a `@2x`-style suffix to the names of
+/// spritesheets with a pixel ratio greater than one, e.g. `sprite`, `sprite@2x`, `sprite@3x`.
+pub fn ratio_file_prefix(file_name_prefix: &str, pixel_ratio: u8) -> String {
+    if pixel_ratio == 1 {
+        file_name_prefix.to_string()
+    } else {
+        format!("{file_name_prefix}@{pixel_ratio}x")
+    }
+}
+
+/// Returns the file name prefix for a page of a spritesheet.
+///
+/// Pages are numbered from zero, e.g. `sprite-0`, `sprite-1`. Use [`ratio_file_prefix`] to add a
+/// pixel ratio suffix, so that MapLibre/Mapbox can find each page at every pixel ratio (e.g.
+/// `sprite-0@2x`).
+pub fn page_file_prefix(file_name_prefix: &str, page: usize) -> String {
+    format!("{file_name_prefix}-{page}")
+}
+
 /// Returns the name (unique id within a spritesheet) taken from a file.
 ///
 /// The unique sprite name is the relative path from `path` to `base_path`
-/// without the file extension.
+/// without the file extension. For PNG and WebP images, any `@2x`-style pixel ratio suffix is also
+/// removed (see [`raster_pixel_ratio`](crate::raster_pixel_ratio)).
 ///
 /// # Errors
 ///
@@ -755,9 +1774,94 @@ pub fn sprite_name<P1: AsRef<Path>, P2: AsRef<Path>>(
     let Some(file_stem) = path.as_ref().file_stem() else {
         return Err(SpreetError::PathError(path.as_ref().to_path_buf()));
     };
+    let file_stem = file_stem.to_string_lossy();
+    let file_stem = if is_raster_path(&path) {
+        split_ratio_suffix(&file_stem).0
+    } else {
+        &file_stem
+    };
     if let Some(parent) = rel_path.parent() {
         Ok(format!("{}", parent.join(file_stem).to_string_lossy()))
     } else {
-        Ok(format!("{}", file_stem.to_string_lossy()))
+        Ok(file_stem.to_string())
+    }
+}
+
+/// Input images whose sprite names collide, found by [`check_sprite_names`] or
+/// [`case_insensitive_name_collisions`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NameCollision {
+    /// The sprite names, in the order they were first given. There's only one name if the images
+    /// have exactly the same name.
+    pub names: Vec<String>,
+    /// The paths of the images, in the order they were given.
+    pub paths: Vec<PathBuf>,
+}
+
+impl fmt::Display for NameCollision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self
+            .names
+            .iter()
+            .map(|name| format!("{name:?}"))
+            .collect::<Vec<_>>();
+        write!(f, "{} ({:?})", names.join(", "), self.paths)
+    }
+}
+
+/// Check that each input image has a different sprite name.
+///
+/// Images are given as `(name, path)` pairs, with names made by [`sprite_name`]. Two images can
+/// have the same name if, for example, a directory has both `icon.svg` and `icon.png`, or two input
+/// directories both have an `icon.svg`. Collecting such images into a map of sprites would silently
+/// keep only one of them.
+///
+/// # Errors
+///
+/// This function will return a [`SpreetError::NameCollisionError`] listing every name that's shared
+/// by more than one image, with the paths of those images.
+pub fn check_sprite_names<S: AsRef<str>, P: AsRef<Path>>(images: &[(S, P)]) -> SpreetResult<()> {
+    let collisions = name_collisions(images, |name| name.to_string());
+    if collisions.is_empty() {
+        Ok(())
+    } else {
+        Err(SpreetError::NameCollisionError(collisions))
+    }
+}
+
+/// Find input images whose sprite names differ only by case, such as `Park` and `park`.
+///
+/// These are different sprites, but the files they're named after can't be kept in the same
+/// directory on case-insensitive file systems, such as the default file systems on macOS and
+/// Windows. Images with exactly the same name are reported by [`check_sprite_names`] instead.
+pub fn case_insensitive_name_collisions<S: AsRef<str>, P: AsRef<Path>>(
+    images: &[(S, P)],
+) -> Vec<NameCollision> {
+    name_collisions(images, str::to_lowercase)
+        .into_iter()
+        .filter(|collision| collision.names.len() > 1)
+        .collect()
+}
+
+/// Group images whose names have the same `key`, returning the groups with more than one image.
+fn name_collisions<S: AsRef<str>, P: AsRef<Path>>(
+    images: &[(S, P)],
+    key: impl Fn(&str) -> String,
+) -> Vec<NameCollision> {
+    let mut groups = BTreeMap::<_, NameCollision>::new();
+    for (name, path) in images {
+        let name = name.as_ref();
+        let group = groups.entry(key(name)).or_insert_with(|| NameCollision {
+            names: Vec::new(),
+            paths: Vec::new(),
+        });
+        if !group.names.iter().any(|n| n == name) {
+            group.names.push(name.to_string());
+        }
+        group.paths.push(path.as_ref().to_path_buf());
     }
+    groups
+        .into_values()
+        .filter(|group| group.paths.len() > 1)
+        .collect()
 }
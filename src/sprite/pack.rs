@@ -0,0 +1,346 @@
+use std::cmp::Reverse;
+
+/// An algorithm for packing sprites into a spritesheet.
+///
+/// Spreet includes several packers: [`CrunchPacker`] (the default), [`ShelfPacker`],
+/// [`SkylinePacker`] and [`MaxRectsPacker`]. Use [`SpritesheetBuilder::packer`] to choose one.
+///
+/// [`SpritesheetBuilder::packer`]: super::SpritesheetBuilder::packer
+pub trait Packer {
+    /// Pack rectangles with the given widths and heights into a bin that's `bin_width` pixels wide
+    /// and `bin_height` pixels high.
+    ///
+    /// Returns the position of the top-left corner of each rectangle, in the same order as `sizes`.
+    /// The position of a rectangle that can't be packed is `None`. Rectangles must not overlap each
+    /// other or lie outside the bin.
+    fn pack(
+        &self,
+        sizes: &[(u32, u32)],
+        bin_width: u32,
+        bin_height: u32,
+    ) -> Vec<Option<(u32, u32)>>;
+}
+
+/// Packs sprites using the [crunch] crate's packer, which splits the free space in the bin into a
+/// tree of rectangles.
+///
+/// This is Spreet's default packer. Packing stops at the first sprite that doesn't fit.
+///
+/// [crunch]: https://crates.io/crates/crunch
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CrunchPacker;
+
+impl Packer for CrunchPacker {
+    fn pack(
+        &self,
+        sizes: &[(u32, u32)],
+        bin_width: u32,
+        bin_height: u32,
+    ) -> Vec<Option<(u32, u32)>> {
+        let items = sizes.iter().enumerate().map(|(i, &(w, h))| {
+            crunch::Item::new(i, w as usize, h as usize, crunch::Rotation::None)
+        });
+        let bin = crunch::Rect::of_size(bin_width as usize, bin_height as usize);
+        let packed = crunch::pack(bin, items).unwrap_or_else(|packed| packed);
+        let mut positions = vec![None; sizes.len()];
+        for crunch::PackedItem { data, rect } in packed {
+            positions[data] = Some((rect.x as u32, rect.y as u32));
+        }
+        positions
+    }
+}
+
+/// Packs sprites in rows (shelves), tallest first, starting a new row when a sprite doesn't fit
+/// on the current one.
+///
+/// Fast, and efficient when sprites have similar heights.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShelfPacker;
+
+impl Packer for ShelfPacker {
+    fn pack(
+        &self,
+        sizes: &[(u32, u32)],
+        bin_width: u32,
+        bin_height: u32,
+    ) -> Vec<Option<(u32, u32)>> {
+        let mut positions = vec![None; sizes.len()];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for i in packing_order(sizes, |(w, h)| (h, w)) {
+            let (w, h) = sizes[i];
+            if w > bin_width {
+                continue;
+            }
+            if x + w > bin_width {
+                y += shelf_height;
+                x = 0;
+                shelf_height = 0;
+            }
+            if y + h > bin_height {
+                continue;
+            }
+            positions[i] = Some((x, y));
+            x += w;
+            shelf_height = shelf_height.max(h);
+        }
+        positions
+    }
+}
+
+/// Packs sprites, tallest first, at the lowest and then left-most position on the skyline formed
+/// by the tops of the sprites already packed.
+///
+/// Usually packs more tightly than [`ShelfPacker`] when sprites have a mixture of heights.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SkylinePacker;
+
+impl Packer for SkylinePacker {
+    fn pack(
+        &self,
+        sizes: &[(u32, u32)],
+        bin_width: u32,
+        bin_height: u32,
+    ) -> Vec<Option<(u32, u32)>> {
+        let mut positions = vec![None; sizes.len()];
+        // Each segment of the skyline is an `(x, y, width)` tuple, ordered from left to right.
+        let mut skyline = vec![(0, 0, bin_width)];
+        for i in packing_order(sizes, |(w, h)| (h, w)) {
+            let (w, h) = sizes[i];
+            let mut best: Option<(u32, u32, usize)> = None;
+            for start in 0..skyline.len() {
+                let x = skyline[start].0;
+                if x + w > bin_width {
+                    break;
+                }
+                // The sprite rests on the highest segment beneath it.
+                let mut y = 0;
+                let mut covered = 0;
+                for &(_, segment_y, segment_width) in &skyline[start..] {
+                    if covered >= w {
+                        break;
+                    }
+                    y = y.max(segment_y);
+                    covered += segment_width;
+                }
+                if y + h <= bin_height
+                    && best.map_or(true, |(best_y, best_x, _)| (y, x) < (best_y, best_x))
+                {
+                    best = Some((y, x, start));
+                }
+            }
+            let Some((y, x, start)) = best else {
+                continue;
+            };
+            positions[i] = Some((x, y));
+
+            // Raise the skyline beneath the sprite, shortening or removing the segments it covers.
+            skyline.insert(start, (x, y + h, w));
+            let right = x + w;
+            let mut k = start + 1;
+            while k < skyline.len() && skyline[k].0 < right {
+                let (segment_x, segment_y, segment_width) = skyline[k];
+                if segment_x + segment_width <= right {
+                    skyline.remove(k);
+                } else {
+                    skyline[k] = (right, segment_y, segment_x + segment_width - right);
+                    k += 1;
+                }
+            }
+            // Merge neighbouring segments of the same height.
+            skyline.dedup_by(|next, prev| {
+                if prev.1 == next.1 {
+                    prev.2 += next.2;
+                    true
+                } else {
+                    false
+                }
+            });
+        }
+        positions
+    }
+}
+
+/// Packs sprites, largest first, into the free rectangle that leaves the shortest leftover side
+/// (the "best short side fit" variant of the MaxRects algorithm).
+///
+/// Usually packs the most tightly of Spreet's packers, but is the slowest.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaxRectsPacker;
+
+impl Packer for MaxRectsPacker {
+    fn pack(
+        &self,
+        sizes: &[(u32, u32)],
+        bin_width: u32,
+        bin_height: u32,
+    ) -> Vec<Option<(u32, u32)>> {
+        let mut positions = vec![None; sizes.len()];
+        // Each free rectangle is an `(x, y, width, height)` tuple. Free rectangles may overlap.
+        let mut free = vec![(0, 0, bin_width, bin_height)];
+        for i in packing_order(sizes, |(w, h)| (w.max(h), w.min(h))) {
+            let (w, h) = sizes[i];
+            let best = free
+                .iter()
+                .filter(|&&(_, _, free_w, free_h)| free_w >= w && free_h >= h)
+                .min_by_key(|&&(x, y, free_w, free_h)| {
+                    let (short, long) =
+                        ((free_w - w).min(free_h - h), (free_w - w).max(free_h - h));
+                    (short, long, y, x)
+                })
+                .copied();
+            let Some((x, y, _, _)) = best else {
+                continue;
+            };
+            positions[i] = Some((x, y));
+
+            // Split every free rectangle that overlaps the sprite into the parts around it.
+            let (right, bottom) = (x + w, y + h);
+            let mut split = Vec::with_capacity(free.len() + 4);
+            for &(fx, fy, fw, fh) in &free {
+                let (f_right, f_bottom) = (fx + fw, fy + fh);
+                if x >= f_right || right <= fx || y >= f_bottom || bottom <= fy {
+                    split.push((fx, fy, fw, fh));
+                    continue;
+                }
+                if x > fx {
+                    split.push((fx, fy, x - fx, fh));
+                }
+                if right < f_right {
+                    split.push((right, fy, f_right - right, fh));
+                }
+                if y > fy {
+                    split.push((fx, fy, fw, y - fy));
+                }
+                if bottom < f_bottom {
+                    split.push((fx, bottom, fw, f_bottom - bottom));
+                }
+            }
+            // Remove free rectangles that are contained within another.
+            let contains = |a: &(u32, u32, u32, u32), b: &(u32, u32, u32, u32)| {
+                a.0 <= b.0 && a.1 <= b.1 && a.0 + a.2 >= b.0 + b.2 && a.1 + a.3 >= b.1 + b.3
+            };
+            free = split
+                .iter()
+                .enumerate()
+                .filter(|&(j, rect)| {
+                    !split.iter().enumerate().any(|(k, other)| {
+                        k != j && contains(other, rect) && (other != rect || k < j)
+                    })
+                })
+                .map(|(_, &rect)| rect)
+                .collect();
+        }
+        positions
+    }
+}
+
+/// The indices of `sizes`, ordered by `key` from largest to smallest, and then by index.
+fn packing_order<K: Ord>(sizes: &[(u32, u32)], key: impl Fn((u32, u32)) -> K) -> Vec<usize> {
+    let mut order = (0..sizes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| (Reverse(key(sizes[i])), i));
+    order
+}
+
+/// Constraints on the size of a spritesheet.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Layout {
+    /// Try bins of any size, not just powers of two.
+    pub(crate) any_size: bool,
+    /// Make the spritesheet square.
+    pub(crate) square: bool,
+    /// Make the spritesheet exactly this many pixels wide.
+    pub(crate) width: Option<u32>,
+}
+
+/// Pack all the rectangles into the smallest bin, no wider or higher than `max_side`, that the
+/// layout allows. `spacing` has already been added to each rectangle's size.
+///
+/// By default, bins with power-of-two sizes are tried from smallest to largest, and the first that
+/// fits all the rectangles is used. With [`Layout::any_size`], bins of several widths are tried,
+/// and the one that the rectangles fill most tightly is used.
+///
+/// Returns the position of each rectangle, or `None` if they can't all be packed.
+pub(crate) fn pack_bin(
+    packer: &dyn Packer,
+    sizes: &[(u32, u32)],
+    layout: &Layout,
+    spacing: u32,
+    max_side: u32,
+) -> Option<Vec<(u32, u32)>> {
+    let min_area = sizes
+        .iter()
+        .map(|&(w, h)| u64::from(w) * u64::from(h))
+        .sum::<u64>();
+    let max_side = u64::from(max_side);
+
+    if let Some(width) = layout.width {
+        return pack_all(packer, sizes, width + spacing, max_side as u32);
+    }
+
+    if !layout.any_size {
+        let mut size: u64 = 2;
+        while size * size * 2 < min_area {
+            size *= 2;
+        }
+        while size <= max_side {
+            let bins = if layout.square {
+                vec![(size, size)]
+            } else {
+                vec![(size, size), (size * 2, size), (size, size * 2)]
+            };
+            for (w, h) in bins {
+                if w <= max_side && h <= max_side && w * h >= min_area {
+                    if let Some(positions) = pack_all(packer, sizes, w as u32, h as u32) {
+                        return Some(positions);
+                    }
+                }
+            }
+            size *= 2;
+        }
+        return None;
+    }
+
+    // Try widths from the narrowest possible up to four times that, growing by 5% each time.
+    let widest = u64::from(sizes.iter().map(|&(w, _)| w).max()?);
+    let total_width = sizes.iter().map(|&(w, _)| u64::from(w)).sum::<u64>();
+    let narrowest = widest.max((min_area as f64).sqrt().ceil() as u64);
+    let mut best: Option<(u64, Vec<(u32, u32)>)> = None;
+    let mut width = narrowest;
+    while width <= max_side && width <= total_width.max(narrowest) && width <= narrowest * 4 {
+        if let Some(positions) = pack_all(packer, sizes, width as u32, max_side as u32) {
+            let (used_width, used_height) =
+                positions
+                    .iter()
+                    .zip(sizes)
+                    .fold((0, 0), |(used_w, used_h), (&(x, y), &(w, h))| {
+                        (used_w.max(u64::from(x + w)), used_h.max(u64::from(y + h)))
+                    });
+            let area = if layout.square {
+                used_width.max(used_height).pow(2)
+            } else {
+                used_width * used_height
+            };
+            if best
+                .as_ref()
+                .map_or(true, |(best_area, _)| area < *best_area)
+            {
+                best = Some((area, positions));
+            }
+        }
+        width = (width + width / 20).max(width + 1);
+    }
+    best.map(|(_, positions)| positions)
+}
+
+/// Pack all the rectangles into a bin, or return `None` if any of them doesn't fit.
+fn pack_all(
+    packer: &dyn Packer,
+    sizes: &[(u32, u32)],
+    bin_width: u32,
+    bin_height: u32,
+) -> Option<Vec<(u32, u32)>> {
+    packer
+        .pack(sizes, bin_width, bin_height)
+        .into_iter()
+        .collect()
+}
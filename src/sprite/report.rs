@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use resvg::usvg::Rect;
+
+use super::{SpriteDescription, Spritesheet};
+use crate::error::SpreetResult;
+use crate::fs::write_atomically;
+
+/// A sprite as it's listed in a report.
+struct ReportEntry<'a> {
+    name: &'a str,
+    description: &'a SpriteDescription,
+    /// The other names of the sprite, if its image is shared (see
+    /// [`SpritesheetBuilder::make_unique`](super::SpritesheetBuilder::make_unique)).
+    aliases: Vec<&'a str>,
+    /// The sprite's image as a PNG `data:` URL, or `None` if the sprite is empty.
+    thumbnail: Option<String>,
+}
+
+impl Spritesheet {
+    /// Generate a self-contained HTML report listing every sprite in the spritesheet.
+    ///
+    /// The report is a table with a thumbnail of each sprite and its size, pixel ratio, other names
+    /// (see [`SpritesheetBuilder::make_unique`](super::SpritesheetBuilder::make_unique)), SDF flag,
+    /// stretch areas and content area, as recorded in the [index](Self::get_index). Thumbnails
+    /// are embedded in the HTML, so the report doesn't need any other files. It's useful for
+    /// reviewing changes to a set of icons.
+    pub fn html_report(&self, title: &str) -> SpreetResult<String> {
+        let entries = self.report_entries()?;
+        let mut html = String::new();
+        let sheet = &self.sheet;
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{}</title>\n<style>{REPORT_STYLE}</style>\n</head>\n<body>\n<h1>{}</h1>\n\
+             <p>{} sprites in a spritesheet of {}×{} pixels.</p>\n<table>\n<thead><tr>\
+             <th>Sprite</th><th>Name</th><th>Size</th><th>Pixel ratio</th><th>Other names</th>\
+             <th>SDF</th><th>Stretch X</th><th>Stretch Y</th><th>Content</th></tr></thead>\n<tbody>\n",
+            escape_html(title),
+            escape_html(title),
+            entries.len(),
+            sheet.width(),
+            sheet.height(),
+        );
+        for entry in &entries {
+            let description = entry.description;
+            let thumbnail = match &entry.thumbnail {
+                // Show the sprite at its size in CSS pixels, as it would appear on a map.
+                Some(url) => format!(
+                    "<img src=\"{url}\" alt=\"\" width=\"{}\" height=\"{}\">",
+                    number(description.width as f32 / description.pixel_ratio as f32),
+                    number(description.height as f32 / description.pixel_ratio as f32),
+                ),
+                None => String::new(),
+            };
+            let _ = writeln!(
+                html,
+                "<tr><td class=\"sprite\">{thumbnail}</td><td>{}</td><td>{}×{}</td><td>{}</td>\
+                 <td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(entry.name),
+                description.width,
+                description.height,
+                description.pixel_ratio,
+                escape_html(&entry.aliases.join(", ")),
+                if description.sdf { "Yes" } else { "" },
+                stretch_x(description),
+                stretch_y(description),
+                content(description),
+            );
+        }
+        html.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+        Ok(html)
+    }
+
+    /// Generate a Markdown report listing every sprite in the spritesheet.
+    ///
+    /// The report contains the same table as the [HTML report](Self::html_report), with the
+    /// thumbnails embedded as images, so that it can be included in a pull request or a README.
+    pub fn markdown_report(&self, title: &str) -> SpreetResult<String> {
+        let entries = self.report_entries()?;
+        let mut markdown = String::new();
+        let _ = write!(
+            markdown,
+            "# {}\n\n{} sprites in a spritesheet of {}×{} pixels.\n\n\
+             | Sprite | Name | Size | Pixel ratio | Other names | SDF | Stretch X | Stretch Y | Content |\n\
+             | --- | --- | --- | --- | --- | --- | --- | --- | --- |\n",
+            escape_markdown(title),
+            entries.len(),
+            self.sheet.width(),
+            self.sheet.height(),
+        );
+        for entry in &entries {
+            let description = entry.description;
+            let thumbnail = match &entry.thumbnail {
+                Some(url) => format!("![]({url})"),
+                None => String::new(),
+            };
+            let _ = writeln!(
+                markdown,
+                "| {thumbnail} | {} | {}×{} | {} | {} | {} | {} | {} | {} |",
+                escape_markdown(entry.name),
+                description.width,
+                description.height,
+                description.pixel_ratio,
+                escape_markdown(&entry.aliases.join(", ")),
+                if description.sdf { "Yes" } else { "" },
+                stretch_x(description),
+                stretch_y(description),
+                content(description),
+            );
+        }
+        Ok(markdown)
+    }
+
+    /// Saves an [HTML report](Self::html_report) to a local file named `file_name_prefix` +
+    /// ".html", titled with the file name.
+    pub fn save_html_report(&self, file_name_prefix: &str) -> SpreetResult<()> {
+        let report = self.html_report(&report_title(file_name_prefix))?;
+        Ok(write_atomically(
+            format!("{file_name_prefix}.html"),
+            report.as_bytes(),
+        )?)
+    }
+
+    /// Saves a [Markdown report](Self::markdown_report) to a local file named `file_name_prefix` +
+    /// ".md", titled with the file name.
+    pub fn save_markdown_report(&self, file_name_prefix: &str) -> SpreetResult<()> {
+        let report = self.markdown_report(&report_title(file_name_prefix))?;
+        Ok(write_atomically(
+            format!("{file_name_prefix}.md"),
+            report.as_bytes(),
+        )?)
+    }
+
+    /// List the sprites in the index, with their other names and thumbnails.
+    fn report_entries(&self) -> SpreetResult<Vec<ReportEntry<'_>>> {
+        // Sprites that share an image have the same position in the spritesheet.
+        let position = |d: &SpriteDescription| (d.page, d.x, d.y, d.width, d.height);
+        let mut names_by_position = BTreeMap::<_, Vec<&str>>::new();
+        for (name, description) in &self.index {
+            names_by_position
+                .entry(position(description))
+                .or_default()
+                .push(name);
+        }
+
+        let mut entries = Vec::with_capacity(self.index.len());
+        for (name, description) in &self.index {
+            let aliases = names_by_position[&position(description)]
+                .iter()
+                .copied()
+                .filter(|alias| alias != name)
+                .collect();
+            let thumbnail = match self.sprite_pixmap(name) {
+                Some(pixmap) => Some(format!(
+                    "data:image/png;base64,{}",
+                    BASE64_STANDARD.encode(pixmap.encode_png()?)
+                )),
+                None => None,
+            };
+            entries.push(ReportEntry {
+                name,
+                description,
+                aliases,
+                thumbnail,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+/// Styles for the HTML report. Thumbnails are shown on a checkerboard so that transparent and white
+/// parts of a sprite can be told apart.
+const REPORT_STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2rem; color: #222; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ddd; padding: 0.4rem 0.6rem; text-align: left; vertical-align: middle; }
+th { background: #f3f3f3; }
+td.sprite { text-align: center; }
+td.sprite img {
+  background: repeating-conic-gradient(#eee 0 25%, #fff 0 50%) 0 0 / 8px 8px;
+  image-rendering: pixelated;
+}
+";
+
+/// The title of a report saved with `file_name_prefix`: the file name, without any directories.
+fn report_title(file_name_prefix: &str) -> String {
+    Path::new(file_name_prefix).file_name().map_or_else(
+        || file_name_prefix.to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+/// A sprite's stretch-x areas, as listed in a report.
+fn stretch_x(description: &SpriteDescription) -> String {
+    list(description.stretch_x.as_deref(), |r| {
+        vec![r.left(), r.right()]
+    })
+}
+
+/// A sprite's stretch-y areas, as listed in a report.
+fn stretch_y(description: &SpriteDescription) -> String {
+    list(description.stretch_y.as_deref(), |r| {
+        vec![r.top(), r.bottom()]
+    })
+}
+
+/// A sprite's content area, as listed in a report.
+fn content(description: &SpriteDescription) -> String {
+    list(Some(description.content.as_slice()), |r| {
+        vec![r.left(), r.top(), r.right(), r.bottom()]
+    })
+}
+
+/// List the edges of some areas in the same format as the index file, e.g. `[10, 14], [40, 44]`.
+fn list(rects: Option<&[Rect]>, edges: impl Fn(&Rect) -> Vec<f32>) -> String {
+    rects
+        .unwrap_or_default()
+        .iter()
+        .map(|rect| {
+            let edges = edges(rect).into_iter().map(number).collect::<Vec<_>>();
+            format!("[{}]", edges.join(", "))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Format a number without a fractional part if it doesn't have one, and otherwise to no more than
+/// three decimal places, matching the index file.
+fn number(num: f32) -> String {
+    ((num * 1e3).round() / 1e3).to_string()
+}
+
+/// Escape the characters that have a special meaning in HTML.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escape the characters that have a special meaning in Markdown, including the `|` that separates
+/// the cells of a table.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '!'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
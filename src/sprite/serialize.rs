@@ -1,6 +1,7 @@
 use resvg::usvg::Rect;
+use serde::de::Error;
 use serde::ser::SerializeSeq;
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Custom Serde field serialiser for [`Rect`].
 ///
@@ -89,6 +90,68 @@ where
     }
 }
 
+/// Custom Serde field deserialiser for [`Rect`].
+///
+/// The inverse of [`serialize_rect`]: reads a `[left, top, right, bottom]` array of numbers.
+pub fn deserialize_rect<'de, D>(deserializer: D) -> Result<Option<Rect>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<[f32; 4]>::deserialize(deserializer)? {
+        Some([left, top, right, bottom]) => Rect::from_ltrb(left, top, right, bottom)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom("invalid content area")),
+        None => Ok(None),
+    }
+}
+
+/// Custom Serde field deserialiser for a vector of [`Rect`]s.
+///
+/// The inverse of [`serialize_stretch_x_area`]: reads an array of `[left, right]` arrays. The
+/// vertical edges of the resulting `Rect`s are set to zero.
+pub fn deserialize_stretch_x_area<'de, D>(deserializer: D) -> Result<Option<Vec<Rect>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Vec<[f32; 2]>>::deserialize(deserializer)? {
+        Some(lines) => lines
+            .into_iter()
+            .map(|[left, right]| {
+                Rect::from_ltrb(left, 0.0, right, 0.0)
+                    .ok_or_else(|| D::Error::custom("invalid stretch-x area"))
+            })
+            .collect::<Result<_, _>>()
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Custom Serde field deserialiser for a vector of [`Rect`]s.
+///
+/// The inverse of [`serialize_stretch_y_area`]: reads an array of `[top, bottom]` arrays. The
+/// horizontal edges of the resulting `Rect`s are set to zero.
+pub fn deserialize_stretch_y_area<'de, D>(deserializer: D) -> Result<Option<Vec<Rect>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Vec<[f32; 2]>>::deserialize(deserializer)? {
+        Some(lines) => lines
+            .into_iter()
+            .map(|[top, bottom]| {
+                Rect::from_ltrb(0.0, top, 0.0, bottom)
+                    .ok_or_else(|| D::Error::custom("invalid stretch-y area"))
+            })
+            .collect::<Result<_, _>>()
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Default pixel ratio for index files that don't specify one, such as simple index files.
+pub fn default_pixel_ratio() -> u8 {
+    1
+}
+
 /// Represents a number, whether integer or floating point, that can be serialised to JSON.
 #[derive(Serialize)]
 #[serde(untagged)]
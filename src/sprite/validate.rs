@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use resvg::tiny_skia::Pixmap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::serialize::default_pixel_ratio;
+use crate::error::SpreetResult;
+use crate::fs::raster_pixel_ratio;
+
+/// A problem with a sprite in an index file, found by [`validate_index`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct IndexProblem {
+    /// The name of the sprite with the problem.
+    pub sprite: String,
+    /// What kind of problem it is.
+    #[serde(rename = "problem")]
+    pub kind: IndexProblemKind,
+    /// A description of the problem.
+    pub message: String,
+}
+
+/// The kinds of problem that [`validate_index`] looks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IndexProblemKind {
+    /// The sprite's description is missing a field, or a field has the wrong type.
+    InvalidDescription,
+    /// The sprite lies (partly) outside the spritesheet image.
+    OutsideImage,
+    /// A `stretchX` area lies (partly) outside the sprite.
+    StretchXOutsideSprite,
+    /// A `stretchX` area ends before it starts, or the areas aren't in order from left to right.
+    StretchXUnordered,
+    /// A `stretchY` area lies (partly) outside the sprite.
+    StretchYOutsideSprite,
+    /// A `stretchY` area ends before it starts, or the areas aren't in order from top to bottom.
+    StretchYUnordered,
+    /// The `content` area lies (partly) outside the sprite.
+    ContentOutsideSprite,
+    /// The `content` area's right or bottom edge is before its left or top edge.
+    ContentUnordered,
+    /// The sprite's `pixelRatio` doesn't match the `@2x`-style suffix of the spritesheet's name.
+    PixelRatioMismatch,
+    /// The sprite's `sdf` flag differs from that of most sprites in the spritesheet.
+    InconsistentSdf,
+}
+
+/// The fields of a sprite's description that are validated. Unlike [`SpriteDescription`], any
+/// numbers are accepted for its areas, so that areas that are out of order can be reported.
+///
+/// [`SpriteDescription`]: super::SpriteDescription
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UncheckedDescription {
+    height: u32,
+    #[serde(default = "default_pixel_ratio")]
+    pixel_ratio: u8,
+    width: u32,
+    x: u32,
+    y: u32,
+    content: Option<[f32; 4]>,
+    stretch_x: Option<Vec<[f32; 2]>>,
+    stretch_y: Option<Vec<[f32; 2]>>,
+    #[serde(default)]
+    sdf: bool,
+}
+
+/// Check a spritesheet's PNG image and index file for problems. See [`validate_index`] for details.
+///
+/// The spritesheet's pixel ratio is read from the `@2x`-style suffix of the PNG image's name, or is
+/// 1 if there isn't one.
+///
+/// # Errors
+///
+/// This function will return an error if either file can't be read, the PNG image can't be decoded,
+/// or the index file isn't a JSON object.
+pub fn validate_spritesheet<P1: AsRef<Path>, P2: AsRef<Path>>(
+    png_path: P1,
+    index_path: P2,
+) -> SpreetResult<Vec<IndexProblem>> {
+    let sheet = Pixmap::decode_png(&std::fs::read(&png_path)?)?;
+    validate_index(
+        &std::fs::read(index_path)?,
+        sheet.width(),
+        sheet.height(),
+        raster_pixel_ratio(png_path),
+    )
+}
+
+/// Check an in-memory JSON index for problems, given the size and pixel ratio of its spritesheet.
+///
+/// Unlike [`Spritesheet::decode`](super::Spritesheet::decode), which stops at the first sprite it
+/// can't use, this checks every sprite, and reports every problem it finds:
+///
+/// - each sprite must lie within the spritesheet image
+/// - each `stretchX` and `stretchY` area must lie within the sprite, end after it starts, and come
+///   after the previous area
+/// - the `content` area must lie within the sprite, and end after it starts
+/// - each sprite's `pixelRatio` must be `pixel_ratio`
+/// - either all the sprites are SDF sprites, or none are
+///
+/// Problems are returned in order of sprite name.
+///
+/// # Errors
+///
+/// This function will return an error if the index isn't a JSON object.
+pub fn validate_index(
+    index_data: &[u8],
+    image_width: u32,
+    image_height: u32,
+    pixel_ratio: u8,
+) -> SpreetResult<Vec<IndexProblem>> {
+    let index: BTreeMap<String, Value> = serde_json::from_slice(index_data)?;
+    let mut problems = Vec::new();
+    let mut descriptions = BTreeMap::new();
+    for (name, value) in index {
+        match UncheckedDescription::deserialize(value) {
+            Ok(description) => {
+                descriptions.insert(name, description);
+            }
+            Err(e) => problems.push(IndexProblem::new(
+                &name,
+                IndexProblemKind::InvalidDescription,
+                e,
+            )),
+        }
+    }
+
+    // Most of the sprites are expected to agree on whether they're SDF sprites.
+    let sdf_count = descriptions.values().filter(|d| d.sdf).count();
+    let sdf = sdf_count * 2 > descriptions.len();
+
+    for (name, description) in &descriptions {
+        let (width, height) = (description.width, description.height);
+        let right = description.x.checked_add(width);
+        let bottom = description.y.checked_add(height);
+        if right.map_or(true, |right| right > image_width)
+            || bottom.map_or(true, |bottom| bottom > image_height)
+        {
+            problems.push(IndexProblem::new(
+                name,
+                IndexProblemKind::OutsideImage,
+                format_args!(
+                    "the sprite ({width}x{height} at {}, {}) lies outside the {image_width}x\
+                     {image_height} image",
+                    description.x, description.y
+                ),
+            ));
+        }
+
+        if let Some(areas) = &description.stretch_x {
+            check_stretch_areas(
+                &mut problems,
+                name,
+                areas,
+                width,
+                "stretchX",
+                IndexProblemKind::StretchXOutsideSprite,
+                IndexProblemKind::StretchXUnordered,
+            );
+        }
+        if let Some(areas) = &description.stretch_y {
+            check_stretch_areas(
+                &mut problems,
+                name,
+                areas,
+                height,
+                "stretchY",
+                IndexProblemKind::StretchYOutsideSprite,
+                IndexProblemKind::StretchYUnordered,
+            );
+        }
+        if let Some(content @ [left, top, right, bottom]) = description.content {
+            if left > right || top > bottom {
+                problems.push(IndexProblem::new(
+                    name,
+                    IndexProblemKind::ContentUnordered,
+                    format_args!("content area {} ends before it starts", area(&content)),
+                ));
+            }
+            let horizontal = within(left, width) && within(right, width);
+            let vertical = within(top, height) && within(bottom, height);
+            if !(horizontal && vertical) {
+                problems.push(IndexProblem::new(
+                    name,
+                    IndexProblemKind::ContentOutsideSprite,
+                    format_args!(
+                        "content area {} lies outside the {width}x{height} sprite",
+                        area(&content)
+                    ),
+                ));
+            }
+        }
+
+        if description.pixel_ratio != pixel_ratio {
+            problems.push(IndexProblem::new(
+                name,
+                IndexProblemKind::PixelRatioMismatch,
+                format_args!(
+                    "pixel ratio {} doesn't match the spritesheet's pixel ratio of {pixel_ratio}",
+                    description.pixel_ratio
+                ),
+            ));
+        }
+
+        if description.sdf != sdf {
+            let message = if description.sdf {
+                "the sprite is an SDF sprite, but most of the sprites aren't"
+            } else {
+                "the sprite isn't an SDF sprite, but most of the sprites are"
+            };
+            problems.push(IndexProblem::new(
+                name,
+                IndexProblemKind::InconsistentSdf,
+                message,
+            ));
+        }
+    }
+
+    problems.sort_by(|a, b| a.sprite.cmp(&b.sprite));
+    Ok(problems)
+}
+
+impl IndexProblem {
+    fn new(sprite: &str, kind: IndexProblemKind, message: impl fmt::Display) -> Self {
+        Self {
+            sprite: sprite.to_string(),
+            kind,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for IndexProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.sprite, self.message)
+    }
+}
+
+/// Check that a sprite's stretch areas lie within `size` and are in order, adding any problems to
+/// `problems`.
+fn check_stretch_areas(
+    problems: &mut Vec<IndexProblem>,
+    name: &str,
+    areas: &[[f32; 2]],
+    size: u32,
+    field: &str,
+    outside: IndexProblemKind,
+    unordered: IndexProblemKind,
+) {
+    let mut previous_end = None;
+    for edges @ &[start, end] in areas {
+        if !(within(start, size) && within(end, size)) {
+            problems.push(IndexProblem::new(
+                name,
+                outside,
+                format_args!(
+                    "{field} area {} lies outside the sprite, which is {size} pixels",
+                    area(edges)
+                ),
+            ));
+        }
+        if start > end {
+            problems.push(IndexProblem::new(
+                name,
+                unordered,
+                format_args!("{field} area {} ends before it starts", area(edges)),
+            ));
+        } else if previous_end.is_some_and(|previous_end| start < previous_end) {
+            problems.push(IndexProblem::new(
+                name,
+                unordered,
+                format_args!(
+                    "{field} area {} starts before the end of the previous area",
+                    area(edges)
+                ),
+            ));
+        }
+        previous_end = Some(end);
+    }
+}
+
+/// Whether a position lies within a sprite that is `size` pixels wide or high.
+fn within(position: f32, size: u32) -> bool {
+    (0.0..=size as f32).contains(&position)
+}
+
+/// Format the edges of an area as they appear in the index file, e.g. `[10, 14]`.
+fn area(edges: &[f32]) -> String {
+    let edges = edges.iter().map(f32::to_string).collect::<Vec<_>>();
+    format!("[{}]", edges.join(", "))
+}
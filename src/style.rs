@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::error::SpreetResult;
+
+/// The layout and paint properties of a style layer whose values are sprite names.
+const ICON_PROPERTIES: [&str; 5] = [
+    "icon-image",
+    "background-pattern",
+    "fill-pattern",
+    "fill-extrusion-pattern",
+    "line-pattern",
+];
+
+/// A reference to a sprite from a MapLibre or Mapbox style, found by [`style_icon_references`].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IconReference {
+    /// A sprite name that's written in the style, such as `"airport"` or
+    /// `["match", ["get", "class"], "rail", "station", "bus-stop"]`.
+    Name(String),
+    /// A sprite name that can only be worked out when the map is displayed, because it depends on
+    /// the feature being drawn. The parts that aren't known are shown as `{property}` tokens, in
+    /// the same way as the style's own templates, or as `{?}`. For example, both `"{class}-15"` and
+    /// `["concat", ["get", "class"], "-15"]` give the reference `{class}-15`.
+    Runtime(String),
+}
+
+impl IconReference {
+    /// Whether this reference could refer to the sprite named `name`.
+    ///
+    /// A [`Name`](Self::Name) only matches the sprite with exactly that name, and a
+    /// [`Runtime`](Self::Runtime) reference matches any sprite whose name has its known parts in
+    /// the same order, so `{class}-15` matches `airport-15` and `rail-15`.
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Name(reference) => reference == name,
+            Self::Runtime(template) => {
+                let parts = template_parts(template);
+                let (first, last) = (parts[0], parts[parts.len() - 1]);
+                let Some(mut rest) = name.strip_prefix(first) else {
+                    return false;
+                };
+                if parts.len() == 1 {
+                    return rest.is_empty();
+                }
+                for part in &parts[1..parts.len() - 1] {
+                    match rest.find(part) {
+                        Some(i) => rest = &rest[i + part.len()..],
+                        None => return false,
+                    }
+                }
+                rest.ends_with(last)
+            }
+        }
+    }
+}
+
+impl fmt::Display for IconReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) | Self::Runtime(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Load a MapLibre or Mapbox style from a JSON file.
+pub fn load_style<P: AsRef<Path>>(path: P) -> SpreetResult<Value> {
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+}
+
+/// Find the sprites referred to by a MapLibre or Mapbox style.
+///
+/// Sprite names are read from the `icon-image` and `*-pattern` properties of the style's layers.
+/// Names are found inside `match`, `case`, `coalesce`, `step` and `image` expressions, as well as
+/// legacy functions with `stops`. Names that depend on the feature being drawn, such as `{token}`
+/// templates, `get` expressions and `concat` expressions that use them, are returned as
+/// [runtime references](IconReference::Runtime).
+///
+/// Returns each reference with the IDs of the layers that use it.
+pub fn style_icon_references(style: &Value) -> BTreeMap<IconReference, BTreeSet<String>> {
+    let mut references = BTreeMap::<_, BTreeSet<String>>::new();
+    let layers = style.get("layers").and_then(Value::as_array);
+    for layer in layers.into_iter().flatten() {
+        let id = layer.get("id").and_then(Value::as_str).unwrap_or_default();
+        for section in ["layout", "paint"] {
+            let Some(properties) = layer.get(section) else {
+                continue;
+            };
+            for property in ICON_PROPERTIES {
+                let Some(value) = properties.get(property) else {
+                    continue;
+                };
+                for reference in property_references(value) {
+                    references
+                        .entry(reference)
+                        .or_default()
+                        .insert(id.to_string());
+                }
+            }
+        }
+    }
+    references
+}
+
+/// The sprites that a property value could refer to.
+fn property_references(value: &Value) -> Vec<IconReference> {
+    match value {
+        // A legacy function, such as `{"stops": [[10, "small"], [14, "large"]]}`.
+        Value::Object(function) => {
+            let stops = function.get("stops").and_then(Value::as_array);
+            let stop_values = stops.into_iter().flatten().filter_map(|stop| stop.get(1));
+            stop_values
+                .chain(function.get("default"))
+                .flat_map(property_references)
+                .collect()
+        }
+        value => expression_references(value),
+    }
+}
+
+/// The sprites that an expression could evaluate to.
+fn expression_references(expression: &Value) -> Vec<IconReference> {
+    match expression {
+        // An empty name means that there's no icon.
+        Value::String(name) if name.is_empty() => Vec::new(),
+        Value::String(name) if name.contains('{') => vec![IconReference::Runtime(name.clone())],
+        Value::String(name) => vec![IconReference::Name(name.clone())],
+        Value::Array(expression) => {
+            let operator = expression.first().and_then(Value::as_str);
+            let args = expression.get(1..).unwrap_or_default();
+            let outputs: Vec<&Value> = match operator {
+                Some("literal") => {
+                    return args.first().map(literal_reference).into_iter().collect()
+                }
+                Some("image" | "to-string" | "string") => args.iter().take(1).collect(),
+                Some("coalesce") => args.iter().collect(),
+                // ["case", condition, output, ..., fallback]
+                Some("case") => args.iter().skip(1).step_by(2).chain(args.last()).collect(),
+                // ["match", input, label, output, ..., fallback]
+                Some("match") => args.iter().skip(2).step_by(2).chain(args.last()).collect(),
+                // ["step", input, output, stop, output, ...]
+                Some("step") => args.iter().skip(1).step_by(2).collect(),
+                Some("concat") => return concat_references(args),
+                Some("get") => {
+                    let property = args.first().and_then(Value::as_str).unwrap_or("?");
+                    return vec![IconReference::Runtime(format!("{{{property}}}"))];
+                }
+                _ => return vec![IconReference::Runtime(String::from("{?}"))],
+            };
+            outputs
+                .into_iter()
+                .flat_map(expression_references)
+                .collect()
+        }
+        _ => vec![IconReference::Runtime(String::from("{?}"))],
+    }
+}
+
+/// The sprite named by a `literal` expression's value.
+fn literal_reference(value: &Value) -> IconReference {
+    match value {
+        Value::String(name) => IconReference::Name(name.clone()),
+        _ => IconReference::Runtime(String::from("{?}")),
+    }
+}
+
+/// The sprites that a `concat` expression could evaluate to. Every combination of its arguments'
+/// values is included.
+fn concat_references(args: &[Value]) -> Vec<IconReference> {
+    let mut references = vec![IconReference::Name(String::new())];
+    for arg in args {
+        let arg_references = match arg {
+            // Numbers and other values are converted to strings.
+            Value::Number(number) => vec![IconReference::Name(number.to_string())],
+            Value::Bool(value) => vec![IconReference::Name(value.to_string())],
+            arg => expression_references(arg),
+        };
+        references = references
+            .iter()
+            .flat_map(|reference| {
+                arg_references.iter().map(move |arg_reference| {
+                    let text = format!("{reference}{arg_reference}");
+                    match (reference, arg_reference) {
+                        (IconReference::Name(_), IconReference::Name(_)) => {
+                            IconReference::Name(text)
+                        }
+                        _ => IconReference::Runtime(text),
+                    }
+                })
+            })
+            .collect();
+    }
+    references
+}
+
+/// Split a runtime reference into the parts between its `{token}`s. There's always one more part
+/// than there are tokens, so the first and last parts may be empty.
+fn template_parts(template: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some((before, after)) = rest.split_once('{') {
+        parts.push(before);
+        rest = after.split_once('}').map_or("", |(_, after)| after);
+    }
+    parts.push(rest);
+    parts
+}
@@ -4,6 +4,7 @@ use std::path::Path;
 use std::process::Command;
 
 use assert_cmd::prelude::*;
+use assert_fs::prelude::*;
 use predicates::prelude::*;
 
 #[test]
@@ -86,6 +87,48 @@ fn spreet_can_output_retina_spritesheet() -> Result<(), Box<dyn std::error::Erro
     Ok(())
 }
 
+#[test]
+fn spreet_can_output_multiple_ratios() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet")?;
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("default"))
+        .arg("--ratios")
+        .arg("1,2")
+        .assert()
+        .success();
+
+    for (suffix, fixture) in [("", "default@1x"), ("@2x", "default@2x")] {
+        let expected_spritesheet =
+            Path::new("tests/fixtures/output").join(format!("{fixture}.png"));
+        let actual_spritesheet =
+            predicate::path::eq_file(temp.join(format!("default{suffix}.png")));
+        let expected_index = Path::new("tests/fixtures/output").join(format!("{fixture}.json"));
+        let actual_index = predicate::path::eq_file(temp.join(format!("default{suffix}.json")));
+
+        assert!(actual_spritesheet.eval(expected_spritesheet.as_path()));
+        assert!(actual_index.eval(expected_index.as_path()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn spreet_rejects_ratios_with_retina() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("default"))
+        .arg("--ratios")
+        .arg("1,2")
+        .arg("--retina")
+        .assert()
+        .failure()
+        .code(2);
+}
+
 #[test]
 fn spreet_can_output_recursive_spritesheet() -> Result<(), Box<dyn std::error::Error>> {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -108,6 +151,174 @@ fn spreet_can_output_recursive_spritesheet() -> Result<(), Box<dyn std::error::E
     Ok(())
 }
 
+#[test]
+fn spreet_can_combine_input_directories_with_prefixes() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("shapes=tests/fixtures/svgs")
+        .arg("tests/fixtures/svgs/recursive")
+        .arg(temp.join("combined"))
+        .assert()
+        .success();
+
+    let index: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.join("combined.json")).unwrap()).unwrap();
+    let names = index.as_object().unwrap().keys().collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "bear",
+            "shapes/another_bicycle",
+            "shapes/bicycle",
+            "shapes/circle"
+        ]
+    );
+}
+
+#[test]
+fn spreet_rejects_duplicate_sprite_names_across_inputs() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg("tests/fixtures/svgs")
+        .arg(temp.join("combined"))
+        .assert()
+        .failure()
+        .code(65)
+        .stderr(predicate::str::contains(
+            "more than one image has the same sprite name: \"another_bicycle\"",
+        ));
+    temp.child("combined.png")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn spreet_can_keep_first_image_with_duplicate_sprite_name() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg("tests/fixtures/svgs")
+        .arg(temp.join("default"))
+        .arg("--on-conflict")
+        .arg("warn")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Warning: more than one image has the same sprite name, keeping the first: \"bicycle\"",
+        ));
+
+    temp.child("default.png").assert(predicate::path::eq_file(
+        "tests/fixtures/output/default@1x.png",
+    ));
+}
+
+#[test]
+fn spreet_warns_about_sprite_names_differing_only_by_case() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("icons/circle.svg")
+        .write_file(Path::new("tests/fixtures/svgs/circle.svg"))
+        .unwrap();
+    temp.child("icons/Circle.svg")
+        .write_file(Path::new("tests/fixtures/svgs/circle.svg"))
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg(temp.join("icons"))
+        .arg(temp.join("default"))
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Warning: sprite names that differ only by case can't be checked out together on \
+             case-insensitive file systems: \"Circle\", \"circle\"",
+        ));
+}
+
+#[test]
+fn spreet_reports_every_invalid_image() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("icons")
+        .copy_from("tests/fixtures/svgs", &["*.svg"])
+        .unwrap();
+    temp.child("icons/broken.svg")
+        .write_str("not an SVG")
+        .unwrap();
+    temp.child("icons/huge.svg")
+        .write_str(r#"<svg xmlns="http://www.w3.org/2000/svg" width="1000000000" height="1"/>"#)
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg(temp.join("icons"))
+        .arg(temp.join("default"))
+        .assert()
+        .failure()
+        .code(65)
+        .stderr(
+            predicate::str::contains("could not load 2 image(s)")
+                .and(predicate::str::contains("broken.svg\": SVG error: "))
+                .and(predicate::str::contains(
+                    "huge.svg\": Image of 1000000000x1 pixels is too large to create",
+                )),
+        );
+    temp.child("default.png").assert(predicate::path::missing());
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg(temp.join("icons"))
+        .arg(temp.join("default"))
+        .arg("--skip-invalid")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Warning: left out 2 image(s) that could not be loaded",
+        ));
+    temp.child("default.png").assert(predicate::path::eq_file(
+        "tests/fixtures/output/default@1x.png",
+    ));
+}
+
+#[test]
+fn spreet_can_filter_input_images_with_patterns() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.copy_from("tests/fixtures/svgs", &["**/*.svg"])
+        .unwrap();
+    temp.child(".spreetignore")
+        .write_str("another_*\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg(temp.path())
+        .arg(temp.join("filtered"))
+        .arg("--recursive")
+        .arg("--exclude")
+        .arg("recursive/")
+        .arg("--include")
+        .arg("*.svg")
+        .assert()
+        .success();
+
+    temp.child("filtered.json").assert(
+        predicate::str::contains("\"bicycle\"")
+            .and(predicate::str::contains("\"circle\""))
+            .and(predicate::str::contains("another_bicycle").not())
+            .and(predicate::str::contains("bear").not()),
+    );
+}
+
+#[test]
+fn spreet_rejects_invalid_input_pattern() {
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg("filtered")
+        .arg("--exclude")
+        .arg("[")
+        .assert()
+        .failure()
+        .code(65)
+        .stderr(predicate::str::contains("could not use the input patterns"));
+}
+
 #[test]
 fn spreet_can_output_unique_retina_spritesheet() -> Result<(), Box<dyn std::error::Error>> {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -223,6 +434,458 @@ fn spreet_can_output_sdf_icons() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn spreet_can_output_sdf_icons_with_custom_options() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("sdf@2x"))
+        .arg("--sdf")
+        .arg("--sdf-buffer")
+        .arg("4")
+        .arg("--sdf-radius")
+        .arg("6")
+        .arg("--sdf-cutoff")
+        .arg("0.5")
+        .arg("--retina")
+        .assert()
+        .success();
+
+    let index = std::fs::read_to_string(temp.join("sdf@2x.json")).unwrap();
+    assert!(index.contains(
+        r#""circle": {
+    "height": 56,
+    "pixelRatio": 2,
+    "width": 56,"#
+    ));
+    assert!(index.contains(r#""sdfBuffer": 8"#));
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+fn spreet_output_does_not_depend_on_jobs() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    for jobs in ["1", "4"] {
+        let mut cmd = Command::cargo_bin("spreet").unwrap();
+        cmd.arg("tests/fixtures/svgs")
+            .arg(temp.join(format!("jobs-{jobs}")))
+            .arg("--jobs")
+            .arg(jobs)
+            .arg("--ratios")
+            .arg("1,2")
+            .arg("--sdf")
+            .arg("--unique")
+            .arg("--recursive")
+            .assert()
+            .success();
+    }
+
+    for file in ["@2x.png", "@2x.json", ".png", ".json"] {
+        let expected = temp.join(format!("jobs-1{file}"));
+        let actual = predicate::path::eq_file(temp.join(format!("jobs-4{file}")));
+        assert!(actual.eval(expected.as_path()));
+    }
+}
+
+#[test]
+fn spreet_can_reuse_rendered_images_from_cache_dir() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    for output in ["first", "second"] {
+        let mut cmd = Command::cargo_bin("spreet").unwrap();
+        cmd.arg("tests/fixtures/svgs")
+            .arg(temp.join(output))
+            .arg("--cache-dir")
+            .arg(temp.join("cache"))
+            .assert()
+            .success();
+
+        let expected_spritesheet = Path::new("tests/fixtures/output/default@1x.png");
+        let actual_spritesheet = predicate::path::eq_file(temp.join(format!("{output}.png")));
+        let expected_index = Path::new("tests/fixtures/output/default@1x.json");
+        let actual_index = predicate::path::eq_file(temp.join(format!("{output}.json")));
+        assert!(actual_spritesheet.eval(expected_spritesheet));
+        assert!(actual_index.eval(expected_index));
+    }
+    // Sprites are cached by the contents of their SVG files, and the two bicycle files differ.
+    assert_eq!(std::fs::read_dir(temp.join("cache")).unwrap().count(), 3);
+}
+
+#[test]
+fn spreet_can_render_duplicate_images_in_parallel_into_an_empty_cache_dir() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = temp.join("input");
+    std::fs::create_dir(&input).unwrap();
+    for i in 0..32 {
+        std::fs::copy(
+            "tests/fixtures/svgs/circle.svg",
+            input.join(format!("circle-{i}.svg")),
+        )
+        .unwrap();
+    }
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg(&input)
+        .arg(temp.join("parallel"))
+        .arg("--cache-dir")
+        .arg(temp.join("cache"))
+        .arg("--jobs")
+        .arg("4")
+        .assert()
+        .success()
+        .stderr("");
+    assert_eq!(std::fs::read_dir(temp.join("cache")).unwrap().count(), 1);
+}
+
+#[test]
+fn spreet_warns_about_images_that_cannot_be_saved_in_cache_dir() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    // The cache directory can't be created, because there's a file in its place.
+    std::fs::write(temp.join("cache"), "").unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("default"))
+        .arg("--cache-dir")
+        .arg(temp.join("cache"))
+        .assert()
+        .success()
+        .stderr(predicate::str::starts_with(
+            "Warning: could not save 3 rendered images in cache directory",
+        ));
+
+    let expected_spritesheet = Path::new("tests/fixtures/output/default@1x.png");
+    let actual_spritesheet = predicate::path::eq_file(temp.join("default.png"));
+    assert!(actual_spritesheet.eval(expected_spritesheet));
+}
+
+#[test]
+fn spreet_rejects_sdf_options_without_sdf() {
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg("sdf")
+        .arg("--sdf-radius")
+        .arg("4")
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn spreet_rejects_sdf_cutoff_outside_zero_to_one() {
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg("sdf")
+        .arg("--sdf")
+        .arg("--sdf-cutoff")
+        .arg("1.5")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("must be a number between 0 and 1"));
+}
+
+#[test]
+fn spreet_can_keep_sprite_positions_with_incremental() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let output = temp.join("incremental");
+
+    let mut cmd = Command::cargo_bin("spreet")?;
+    cmd.arg("tests/fixtures/svgs")
+        .arg(&output)
+        .assert()
+        .success();
+    let previous: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.join("incremental.json"))?)?;
+
+    let mut cmd = Command::cargo_bin("spreet")?;
+    cmd.arg("tests/fixtures/svgs")
+        .arg(&output)
+        .arg("--recursive")
+        .arg("--incremental")
+        .assert()
+        .success();
+    let index: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.join("incremental.json"))?)?;
+
+    for (name, sprite) in previous.as_object().unwrap() {
+        assert_eq!(index[name]["x"], sprite["x"]);
+        assert_eq!(index[name]["y"], sprite["y"]);
+    }
+    assert!(index.get("recursive/bear").is_some());
+
+    Ok(())
+}
+
+#[test]
+fn spreet_can_extract_sprites() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet")?;
+    cmd.arg("extract")
+        .arg("tests/fixtures/output/recursive@1x.png")
+        .arg(temp.path())
+        .assert()
+        .success();
+
+    for name in ["another_bicycle", "bicycle", "circle", "recursive/bear"] {
+        assert!(temp.join(format!("{name}.png")).is_file());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn spreet_can_extract_unique_retina_sprites() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet")?;
+    cmd.arg("extract")
+        .arg("tests/fixtures/output/unique@2x.png")
+        .arg(temp.path())
+        .arg("--index")
+        .arg("tests/fixtures/output/unique@2x.json")
+        .assert()
+        .success();
+
+    // Both names for the shared bicycle image are extracted.
+    let bicycle = std::fs::read(temp.join("bicycle@2x.png"))?;
+    let another_bicycle = std::fs::read(temp.join("another_bicycle@2x.png"))?;
+    assert_eq!(bicycle, another_bicycle);
+    assert!(temp.join("circle@2x.png").is_file());
+
+    Ok(())
+}
+
+#[test]
+fn spreet_rejects_non_existent_spritesheet_to_extract() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("extract")
+        .arg("does_not_exist.png")
+        .arg(temp.path())
+        .assert()
+        .failure()
+        .code(2)
+        .stderr("error: invalid value 'does_not_exist.png' for '<SPRITESHEET>': must be an existing file\n\nFor more information, try '--help'.\n");
+}
+
+#[test]
+fn spreet_can_merge_spritesheets_with_prefixes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet")?;
+    cmd.arg("merge")
+        .arg("tests/fixtures/output/default@1x.png")
+        .arg("extra=tests/fixtures/output/recursive@1x.png")
+        .arg(temp.join("merged"))
+        .arg("--unique")
+        .assert()
+        .success();
+
+    let index: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.join("merged.json"))?)?;
+    let mut names = index.as_object().unwrap().keys().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "another_bicycle",
+            "bicycle",
+            "circle",
+            "extra/another_bicycle",
+            "extra/bicycle",
+            "extra/circle",
+            "extra/recursive/bear"
+        ]
+    );
+    assert!(temp.join("merged.png").is_file());
+
+    Ok(())
+}
+
+#[test]
+fn spreet_rejects_merge_with_duplicate_sprite_names() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("merge")
+        .arg("tests/fixtures/output/default@1x.png")
+        .arg("tests/fixtures/output/recursive@1x.png")
+        .arg(temp.join("merged"))
+        .assert()
+        .failure()
+        .code(65)
+        .stderr("Error: more than one spritesheet has a sprite named \"another_bicycle\"\n");
+}
+
+#[test]
+fn spreet_can_merge_duplicate_sprite_names_with_policy() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("merge")
+        .arg("tests/fixtures/output/default@1x.png")
+        .arg("tests/fixtures/output/recursive@1x.png")
+        .arg(temp.join("merged"))
+        .arg("--on-conflict")
+        .arg("first")
+        .assert()
+        .success();
+}
+
+#[test]
+fn spreet_can_merge_spritesheets_with_different_ratios() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("merge")
+        .arg("tests/fixtures/output/default@1x.png")
+        .arg("retina=tests/fixtures/output/default@2x.png")
+        .arg(temp.join("merged"))
+        .assert()
+        .failure()
+        .code(65);
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("merge")
+        .arg("tests/fixtures/output/default@1x.png")
+        .arg("retina=tests/fixtures/output/default@2x.png")
+        .arg(temp.join("merged@2x"))
+        .arg("--ratio")
+        .arg("2")
+        .assert()
+        .success();
+}
+
+#[test]
+fn spreet_can_diff_spritesheets() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    // Sprites that have only moved within the spritesheet aren't differences.
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("diff")
+        .arg("tests/fixtures/output/default@1x.png")
+        .arg("tests/fixtures/output/unique@1x.png")
+        .assert()
+        .success()
+        .stdout("");
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("diff")
+        .arg("tests/fixtures/output/default@1x.png")
+        .arg("tests/fixtures/output/recursive@1x.png")
+        .arg("--diff-image")
+        .arg(temp.join("diff.png"))
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("added: recursive/bear\n");
+    temp.child("diff.png").assert(predicate::path::is_file());
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("diff")
+        .arg("tests/fixtures/output/default@1x.png")
+        .arg("tests/fixtures/output/default@2x.png")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "resized: circle (20x20 -> 40x40)\n",
+        ));
+}
+
+#[test]
+fn spreet_can_validate_spritesheet() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("validate")
+        .arg("tests/fixtures/output/stretchable@2x.png")
+        .assert()
+        .success()
+        .stdout("[]\n");
+
+    // Without an `@2x` suffix, the spritesheet's pixel ratio is 1.
+    let spritesheet = temp.child("stretchable.png");
+    spritesheet
+        .write_file(Path::new("tests/fixtures/output/stretchable@2x.png"))
+        .unwrap();
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("validate")
+        .arg(spritesheet.path())
+        .arg("--index")
+        .arg("tests/fixtures/output/stretchable@2x.json")
+        .assert()
+        .failure()
+        .code(65)
+        .stdout(predicate::str::contains(
+            r#""sprite": "shield-illustrator",
+    "problem": "pixel-ratio-mismatch","#,
+        ));
+}
+
+#[test]
+fn spreet_can_check_style_icons() {
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("check-style")
+        .arg("tests/fixtures/styles/style.json")
+        .arg("--index")
+        .arg("tests/fixtures/output/recursive@1x.json")
+        .assert()
+        .failure()
+        .code(65)
+        .stdout(
+            "missing: road (used by cycleways)\n\
+             missing: shop (used by shops)\n\
+             unused: recursive/bear\n\
+             runtime: shop-{kind} (used by shops, matches 0 sprites)\n\
+             runtime: {class}_bicycle (used by labels, matches 1 sprite)\n",
+        );
+}
+
+#[test]
+fn spreet_can_check_style_icons_against_input_directory() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let style = temp.child("style.json");
+    style
+        .write_str(r#"{"layers": [{"id": "bikes", "layout": {"icon-image": "{type}bicycle"}}]}"#)
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("check-style")
+        .arg(style.path())
+        .arg("--input")
+        .arg("tests/fixtures/svgs")
+        .assert()
+        .success()
+        .stdout("unused: circle\nruntime: {type}bicycle (used by bikes, matches 2 sprites)\n");
+}
+
+#[test]
+fn spreet_checks_style_icons_with_the_same_names_as_a_build() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let style = temp.child("style.json");
+    style
+        .write_str(r#"{"layers": [{"id": "bikes", "layout": {"icon-image": "icons/bicycle"}}]}"#)
+        .unwrap();
+
+    // Prefixes and exclude patterns are applied to the input directory, as they are for a build.
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("check-style")
+        .arg(style.path())
+        .arg("--input")
+        .arg("icons=tests/fixtures/svgs")
+        .arg("--exclude")
+        .arg("circle.svg")
+        .assert()
+        .success()
+        .stdout("unused: icons/another_bicycle\n");
+}
+
 #[test]
 fn spreet_rejects_non_existent_input_directory() {
     let mut cmd = Command::cargo_bin("spreet").unwrap();
@@ -231,7 +894,7 @@ fn spreet_rejects_non_existent_input_directory() {
         .assert()
         .failure()
         .code(2)
-        .stderr("error: invalid value 'does_not_exist' for '<INPUT>': must be an existing directory\n\nFor more information, try '--help'.\n");
+        .stderr("error: invalid value 'does_not_exist' for '<INPUT>...': must be an existing directory\n\nFor more information, try '--help'.\n");
 }
 
 #[test]
@@ -249,6 +912,21 @@ fn spreet_rejects_zero_ratio() {
         .stderr("error: invalid value '0' for '--ratio <RATIO>': must be greater than one\n\nFor more information, try '--help'.\n");
 }
 
+#[test]
+fn spreet_rejects_zero_max_size() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("default"))
+        .arg("--max-size")
+        .arg("0")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr("error: invalid value '0' for '--max-size <PIXELS>': must be greater than zero\n\nFor more information, try '--help'.\n");
+}
+
 #[test]
 fn spreet_rejects_negative_ratio() {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -264,6 +942,68 @@ fn spreet_rejects_negative_ratio() {
         .stderr("error: invalid value ' -3' for '--ratio <RATIO>': invalid digit found in string\n\nFor more information, try '--help'.\n");
 }
 
+#[test]
+fn spreet_can_split_spritesheet_into_pages() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("paged"))
+        .arg("--recursive")
+        .arg("--max-size")
+        .arg("32")
+        .assert()
+        .success();
+
+    temp.child("paged-0.png").assert(predicate::path::exists());
+    temp.child("paged-0.json").assert(predicate::path::exists());
+    temp.child("paged-1.png").assert(predicate::path::exists());
+    temp.child("paged-1.json").assert(predicate::path::exists());
+    temp.child("paged.png").assert(predicate::path::missing());
+}
+
+#[test]
+fn spreet_can_output_combined_index_for_pages() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("paged"))
+        .arg("--recursive")
+        .arg("--ratios")
+        .arg("1,2")
+        .arg("--max-size")
+        .arg("64")
+        .arg("--combined-index")
+        .assert()
+        .success();
+
+    temp.child("paged-0.png").assert(predicate::path::exists());
+    temp.child("paged-0@2x.png")
+        .assert(predicate::path::exists());
+    temp.child("paged-0.json")
+        .assert(predicate::path::missing());
+    temp.child("paged.json")
+        .assert(predicate::str::contains(r#""page": 0"#));
+    temp.child("paged@2x.json")
+        .assert(predicate::str::contains(r#""page": 1"#));
+}
+
+#[test]
+fn spreet_rejects_sprites_larger_than_max_size() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("paged"))
+        .arg("--max-size")
+        .arg("16")
+        .assert()
+        .failure()
+        .code(65)
+        .stderr(predicate::str::contains("pages of 16x16 pixels"));
+}
+
 #[test]
 fn spreet_accepts_pngs_wrapped_in_svgs() {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -284,6 +1024,44 @@ fn spreet_accepts_pngs_wrapped_in_svgs() {
     assert!(actual_index.eval(expected_index));
 }
 
+#[test]
+fn spreet_can_include_raster_images() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/rasters")
+        .arg(temp.join("rasters"))
+        .arg("--ratios")
+        .arg("1,2")
+        .assert()
+        .failure()
+        .code(66);
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/rasters")
+        .arg(temp.join("rasters"))
+        .arg("--ratios")
+        .arg("1,2")
+        .arg("--raster")
+        .assert()
+        .success();
+
+    let index = std::fs::read_to_string(temp.join("rasters.json")).unwrap();
+    assert!(index.contains(
+        r#""iceland_flag": {
+    "height": 115,
+    "pixelRatio": 1,
+    "width": 160,"#
+    ));
+    let index = std::fs::read_to_string(temp.join("rasters@2x.json")).unwrap();
+    assert!(index.contains(
+        r#""sweden_flag": {
+    "height": 400,
+    "pixelRatio": 2,
+    "width": 640,"#
+    ));
+}
+
 #[test]
 fn spreet_accepts_zero_spacing() -> Result<(), Box<dyn std::error::Error>> {
     let temp = assert_fs::TempDir::new().unwrap();
@@ -385,3 +1163,487 @@ fn spreet_can_output_retina_spritesheet_with_spacing() -> Result<(), Box<dyn std
 
     Ok(())
 }
+
+#[test]
+fn spreet_can_use_packer_and_report_fill_ratio() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/stretchable")
+        .arg(temp.join("packed"))
+        .arg("--packer")
+        .arg("max-rects")
+        .arg("--any-size")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "packed.png: 84x43 pixels, 88.3% filled",
+        ));
+
+    temp.child("packed.png").assert(predicate::path::exists());
+    temp.child("packed.json").assert(predicate::path::exists());
+}
+
+#[test]
+fn spreet_can_output_spritesheet_with_fixed_width() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/stretchable")
+        .arg(temp.join("narrow"))
+        .arg("--packer")
+        .arg("shelf")
+        .arg("--width")
+        .arg("50")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("narrow.png: 50x"));
+}
+
+#[test]
+fn spreet_can_output_sprite_reports() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("reported"))
+        .arg("--ratios")
+        .arg("1,2")
+        .arg("--report")
+        .arg("html,markdown")
+        .assert()
+        .success();
+    for prefix in ["reported", "reported@2x"] {
+        temp.child(format!("{prefix}.html"))
+            .assert(predicate::str::contains("<td>circle</td>"));
+        temp.child(format!("{prefix}.md"))
+            .assert(predicate::str::starts_with(format!("# {prefix}\n")));
+    }
+}
+
+#[test]
+fn spreet_rejects_unknown_packer() {
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg("packed")
+        .arg("--packer")
+        .arg("guillotine")
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn spreet_can_include_only_images_in_allowlist() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let allowlist = temp.child("allowlist.txt");
+    allowlist
+        .write_str("# Icons for the cycling map\ncircle\n\nbicycle\nmissing\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("pruned"))
+        .arg("--allowlist")
+        .arg(allowlist.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("uses \"missing\", which isn't in"));
+    let index: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.join("pruned.json")).unwrap()).unwrap();
+    assert_eq!(
+        index.as_object().unwrap().keys().collect::<Vec<_>>(),
+        ["bicycle", "circle"]
+    );
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("tests/fixtures/svgs")
+        .arg(temp.join("strict"))
+        .arg("--allowlist")
+        .arg(allowlist.path())
+        .arg("--strict")
+        .assert()
+        .failure()
+        .code(65);
+    temp.child("strict.png").assert(predicate::path::missing());
+}
+
+#[test]
+fn spreet_can_include_only_images_used_by_style() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let style = temp.child("style.json");
+    style
+        .write_str(r#"{"layers": [{"id": "poi", "layout": {"icon-image": ["match", ["get", "kind"], "dot", "circle", "unused"]}}]}"#)
+        .unwrap();
+    let config = temp.child("spreet.toml");
+    config
+        .write_str(&format!(
+            "[builds.styled]\n\
+             input = '{}'\n\
+             output = 'styled'\n\
+             style = 'style.json'\n\
+             strict = true\n",
+            std::env::current_dir()
+                .unwrap()
+                .join("tests/fixtures/svgs")
+                .display()
+        ))
+        .unwrap();
+
+    // The style is read relative to the config file.
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("build")
+        .arg(config.path())
+        .assert()
+        .failure()
+        .code(65)
+        .stderr(predicate::str::contains("\"unused\""));
+
+    style
+        .write_str(r#"{"layers": [{"id": "poi", "layout": {"icon-image": "{kind}cycle"}}]}"#)
+        .unwrap();
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("build").arg(config.path()).assert().success();
+    let index: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(temp.join("styled.json")).unwrap()).unwrap();
+    assert_eq!(
+        index.as_object().unwrap().keys().collect::<Vec<_>>(),
+        ["another_bicycle", "bicycle"]
+    );
+}
+
+#[test]
+fn spreet_can_build_spritesheets_from_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let input = std::env::current_dir().unwrap().join("tests/fixtures/svgs");
+    let config = temp.child("spreet.toml");
+    config
+        .write_str(&format!(
+            "[builds.default]\n\
+             input = '{0}'\n\
+             output = 'default'\n\
+             \n\
+             [builds.unique]\n\
+             input = '{0}'\n\
+             output = 'out/unique'\n\
+             ratios = [2]\n\
+             unique = true\n",
+            input.display()
+        ))
+        .unwrap();
+    temp.child("out").create_dir_all().unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("build").arg(config.path()).assert().success();
+
+    temp.child("default.png").assert(predicate::path::eq_file(
+        "tests/fixtures/output/default@1x.png",
+    ));
+    temp.child("default.json").assert(predicate::path::eq_file(
+        "tests/fixtures/output/default@1x.json",
+    ));
+    temp.child("out/unique@2x.png")
+        .assert(predicate::path::eq_file(
+            "tests/fixtures/output/unique@2x.png",
+        ));
+    temp.child("out/unique@2x.json")
+        .assert(predicate::path::eq_file(
+            "tests/fixtures/output/unique@2x.json",
+        ));
+}
+
+#[test]
+fn spreet_can_build_spritesheet_from_several_inputs_in_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("icons")
+        .copy_from("tests/fixtures/svgs", &["*.svg"])
+        .unwrap();
+    temp.child("more")
+        .copy_from("tests/fixtures/svgs/recursive", &["*.svg"])
+        .unwrap();
+    temp.child("spreet.toml")
+        .write_str("[builds.default]\ninput = ['icons', 'more=more']\noutput = 'default'\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("build")
+        .arg(temp.join("spreet.toml"))
+        .assert()
+        .success();
+
+    temp.child("default.json").assert(
+        predicate::str::contains("\"more/bear\"").and(predicate::str::contains("\"circle\"")),
+    );
+}
+
+#[test]
+fn spreet_can_build_spritesheets_from_json_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.copy_from("tests/fixtures/svgs", &["*.svg"]).unwrap();
+    temp.child("spreet.json")
+        .write_str(r#"{"builds": {"default": {"input": ".", "output": "default"}}}"#)
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("build")
+        .arg(temp.join("spreet.json"))
+        .assert()
+        .success();
+
+    temp.child("default.png").assert(predicate::path::eq_file(
+        "tests/fixtures/output/default@1x.png",
+    ));
+}
+
+#[test]
+fn spreet_reads_spreet_toml_by_default() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("icons")
+        .copy_from("tests/fixtures/svgs", &["*.svg"])
+        .unwrap();
+    temp.child("spreet.toml")
+        .write_str("[builds.default]\ninput = 'icons'\noutput = 'default'\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.current_dir(temp.path()).arg("build").assert().success();
+
+    temp.child("default.png").assert(predicate::path::eq_file(
+        Path::new("tests/fixtures/output/default@1x.png")
+            .canonicalize()
+            .unwrap(),
+    ));
+}
+
+#[test]
+fn spreet_rejects_invalid_build_in_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    temp.child("icons")
+        .copy_from("tests/fixtures/svgs", &["*.svg"])
+        .unwrap();
+    temp.child("spreet.toml")
+        .write_str("[builds.default]\ninput = 'icons'\noutput = 'default'\nshiny = true\n")
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin("spreet").unwrap();
+    cmd.arg("build")
+        .arg(temp.join("spreet.toml"))
+        .assert()
+        .failure()
+        .code(78)
+        .stderr(predicate::str::contains("invalid build \"default\" in"))
+        .stderr(predicate::str::contains("--shiny"));
+
+    temp.child("default.png").assert(predicate::path::missing());
+}
+
+#[test]
+fn spreet_can_watch_input_directory() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let icons = temp.child("icons");
+    icons.copy_from("tests/fixtures/svgs", &["*.svg"]).unwrap();
+
+    let mut child = Command::cargo_bin("spreet")
+        .unwrap()
+        .arg(icons.path())
+        .arg(temp.join("watched"))
+        .arg("--watch")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let wait_for = |condition: &dyn Fn() -> bool| {
+        let start = std::time::Instant::now();
+        while !condition() && start.elapsed() < std::time::Duration::from_secs(20) {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        condition()
+    };
+    let index = temp.child("watched.json");
+    let sprite_count = || {
+        std::fs::read(index.path())
+            .ok()
+            .and_then(|json| serde_json::from_slice::<serde_json::Value>(&json).ok())
+            .and_then(|index| Some(index.as_object()?.len()))
+    };
+    let built = wait_for(&|| sprite_count() == Some(3));
+
+    // An invalid image is reported without stopping, and a valid one is added to the spritesheet.
+    icons.child("new.svg").write_str("<svg").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let still_running = child.try_wait().unwrap().is_none();
+    std::fs::copy(
+        "tests/fixtures/svgs/circle.svg",
+        icons.child("new.svg").path(),
+    )
+    .unwrap();
+    let rebuilt = wait_for(&|| sprite_count() == Some(4));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(built);
+    assert!(still_running);
+    assert!(rebuilt);
+}
+
+#[test]
+fn spreet_leaves_out_spritesheets_saved_in_a_raster_input_directory() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let icons = temp.child("icons");
+    icons.copy_from("tests/fixtures/rasters", &["*"]).unwrap();
+
+    for _ in 0..2 {
+        let mut cmd = Command::cargo_bin("spreet").unwrap();
+        cmd.arg(icons.path())
+            .arg(icons.join("sprite"))
+            .arg("--ratios")
+            .arg("1,2")
+            .arg("--raster")
+            .assert()
+            .success();
+    }
+
+    for index in ["sprite.json", "sprite@2x.json"] {
+        let index = std::fs::read(icons.join(index)).unwrap();
+        let index = serde_json::from_slice::<serde_json::Value>(&index).unwrap();
+        let names = index.as_object().unwrap().keys().collect::<Vec<_>>();
+        assert_eq!(names, ["iceland_flag", "sweden_flag"]);
+    }
+}
+
+#[test]
+fn spreet_does_not_rebuild_when_watched_spritesheet_is_saved_in_an_input_directory() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let icons = temp.child("icons");
+    icons.copy_from("tests/fixtures/rasters", &["*"]).unwrap();
+    let log = temp.child("log");
+
+    let mut child = Command::cargo_bin("spreet")
+        .unwrap()
+        .arg(icons.path())
+        .arg(icons.join("sprite"))
+        .arg("--raster")
+        .arg("--watch")
+        .stdout(std::fs::File::create(log.path()).unwrap())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let builds = || {
+        std::fs::read_to_string(log.path())
+            .unwrap_or_default()
+            .matches("Saved")
+            .count()
+    };
+    let start = std::time::Instant::now();
+    while builds() == 0 && start.elapsed() < std::time::Duration::from_secs(20) {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    // Saving the spritesheet doesn't trigger another build.
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+    let builds = builds();
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert_eq!(builds, 1);
+}
+
+#[test]
+fn spreet_can_serve_spritesheet_preview() {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let temp = assert_fs::TempDir::new().unwrap();
+    let mut child = Command::cargo_bin("spreet")
+        .unwrap()
+        .arg("serve")
+        .arg("tests/fixtures/svgs")
+        .arg(temp.join("preview"))
+        .args(["--port", "0"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    let address = line
+        .trim_end()
+        .rsplit_once("http://")
+        .map(|(_, url)| url.trim_end_matches('/').to_string());
+
+    let get = |path: &str| {
+        let mut stream = std::net::TcpStream::connect(address.as_ref()?).ok()?;
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").ok()?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).ok()?;
+        let (head, body) = response.split_once("\r\n\r\n")?;
+        Some((head.lines().next()?.to_string(), body.to_string()))
+    };
+    // The preview is available once the spritesheet has been built.
+    let start = std::time::Instant::now();
+    let mut preview = None;
+    while preview.is_none() && start.elapsed() < std::time::Duration::from_secs(20) {
+        preview = get("/preview.json")
+            .and_then(|(_, body)| serde_json::from_str::<serde_json::Value>(&body).ok())
+            .filter(|preview| preview["version"] == 1);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    let page = get("/");
+    let index = preview.as_ref().and_then(|preview| {
+        get(&format!(
+            "/{}",
+            preview["spritesheets"][0]["index"].as_str()?
+        ))
+    });
+    let missing = get("/missing");
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(address.is_some_and(|address| address.starts_with("localhost:")));
+    let preview = preview.unwrap();
+    assert_eq!(preview["spritesheets"][0]["pixelRatio"], 1);
+    let (status, page) = page.unwrap();
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(page.contains("preview.json"));
+    let (status, index) = index.unwrap();
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&index).unwrap(),
+        serde_json::from_slice::<serde_json::Value>(
+            &std::fs::read(temp.join("preview.json")).unwrap()
+        )
+        .unwrap()
+    );
+    assert_eq!(missing.unwrap().0, "HTTP/1.1 404 Not Found");
+}
+
+#[test]
+fn spreet_can_report_near_duplicates() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let icons = temp.child("icons");
+    icons.copy_from("tests/fixtures/svgs", &["*.svg"])?;
+    icons.child("small_circle.svg").write_str(
+        r##"<svg viewBox="0 0 20 20" xmlns="http://www.w3.org/2000/svg">
+  <circle cx="10" cy="10" r="9.9" fill="#f00"/>
+</svg>"##,
+    )?;
+
+    let mut cmd = Command::cargo_bin("spreet")?;
+    cmd.arg(icons.path())
+        .arg(temp.join("near_duplicates"))
+        .arg("--near-duplicates")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "near duplicates: circle and small_circle (",
+        ))
+        .stdout(predicate::str::contains("bicycle").not());
+
+    let mut cmd = Command::cargo_bin("spreet")?;
+    cmd.arg(icons.path())
+        .arg(temp.join("no_near_duplicates"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("near duplicates").not());
+
+    Ok(())
+}
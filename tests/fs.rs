@@ -1,7 +1,11 @@
 use std::path::Path;
 
 use assert_matches::assert_matches;
-use spreet::{get_svg_input_paths, SpreetError};
+use spreet::{
+    get_image_input_paths, get_svg_input_paths, get_svg_input_paths_with_options, load_images,
+    load_index, load_raster, raster_pixel_ratio, write_atomically, InputImage, InputOptions,
+    SpreetError,
+};
 
 #[test]
 fn get_svg_input_paths_returns_non_recursive_results() {
@@ -32,6 +36,75 @@ fn get_svg_input_paths_returns_recursive_results() {
     );
 }
 
+#[test]
+fn get_svg_input_paths_with_options_filters_paths() {
+    let options = InputOptions {
+        recursive: true,
+        include: vec![String::from("*bicycle.svg"), String::from("recursive/")],
+        exclude: vec![String::from("another_*")],
+    };
+    let mut input_paths =
+        get_svg_input_paths_with_options(Path::new("tests/fixtures/svgs"), &options).unwrap();
+    input_paths.sort();
+    assert_eq!(
+        input_paths,
+        vec![
+            Path::new("tests/fixtures/svgs/bicycle.svg"),
+            Path::new("tests/fixtures/svgs/recursive/bear.svg"),
+        ]
+    );
+}
+
+#[test]
+fn get_svg_input_paths_with_options_reads_ignore_file() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    for name in [
+        "circle.svg",
+        "circle-draft.svg",
+        "old/circle.svg",
+        "new/circle.svg",
+    ] {
+        let path = temp.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::copy("tests/fixtures/svgs/circle.svg", path).unwrap();
+    }
+    std::fs::write(
+        temp.path().join(".spreetignore"),
+        "# Drafts\n*-draft.svg\nold/\n",
+    )
+    .unwrap();
+
+    let options = InputOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    let mut input_paths = get_svg_input_paths_with_options(temp.path(), &options).unwrap();
+    input_paths.sort();
+    assert_eq!(
+        input_paths,
+        vec![
+            temp.path().join("circle.svg"),
+            temp.path().join("new/circle.svg")
+        ]
+    );
+
+    // Functions without options don't read the ignore file.
+    let input_paths = get_svg_input_paths(temp.path(), true).unwrap();
+    assert_eq!(input_paths.len(), 4);
+}
+
+#[test]
+fn get_svg_input_paths_with_options_returns_error_for_invalid_pattern() {
+    let options = InputOptions {
+        exclude: vec![String::from("[")],
+        ..Default::default()
+    };
+    assert_matches!(
+        get_svg_input_paths_with_options(Path::new("tests/fixtures/svgs"), &options),
+        Err(SpreetError::PatternError(_))
+    );
+}
+
 #[test]
 fn get_svg_input_paths_returns_error_when_path_does_not_exist() {
     assert_matches!(
@@ -47,3 +120,115 @@ fn get_svg_input_paths_returns_error_when_path_is_file() {
         Err(SpreetError::IoError(_))
     );
 }
+
+#[test]
+fn get_image_input_paths_includes_raster_images() {
+    let mut input_paths = get_image_input_paths(Path::new("tests/fixtures/pngs"), false).unwrap();
+    input_paths.sort();
+    assert_eq!(
+        input_paths,
+        vec![
+            Path::new("tests/fixtures/pngs/iceland_flag.png"),
+            Path::new("tests/fixtures/pngs/iceland_flag.svg"),
+            Path::new("tests/fixtures/pngs/sweden_flag.png"),
+            Path::new("tests/fixtures/pngs/sweden_flag.svg"),
+        ]
+    );
+}
+
+#[test]
+fn load_images_returns_result_for_every_path() {
+    let results = load_images(&[
+        "tests/fixtures/svgs/circle.svg",
+        "tests/fixtures/pngs/sweden_flag.png",
+        "tests/fixtures/svgs/does_not_exist.svg",
+        "tests/fixtures/styles/style.json",
+    ]);
+    let paths = results.iter().map(|(path, _)| path).collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        [
+            Path::new("tests/fixtures/svgs/circle.svg"),
+            Path::new("tests/fixtures/pngs/sweden_flag.png"),
+            Path::new("tests/fixtures/svgs/does_not_exist.svg"),
+            Path::new("tests/fixtures/styles/style.json"),
+        ]
+    );
+    assert_matches!(results[0].1, Ok(InputImage::Svg(_)));
+    assert_matches!(results[1].1, Ok(InputImage::Raster(_)));
+    assert_matches!(results[2].1, Err(SpreetError::IoError(_)));
+    assert_matches!(results[3].1, Err(SpreetError::SvgError(_)));
+}
+
+#[test]
+fn load_raster_decodes_png_and_webp_images() {
+    let png = load_raster(Path::new("tests/fixtures/rasters/iceland_flag@2x.png")).unwrap();
+    assert_eq!((png.width(), png.height()), (320, 230));
+    let webp = load_raster(Path::new("tests/fixtures/rasters/sweden_flag.webp")).unwrap();
+    assert_eq!((webp.width(), webp.height()), (320, 200));
+}
+
+#[test]
+fn load_raster_returns_error_for_svg() {
+    assert_matches!(
+        load_raster(Path::new("tests/fixtures/svgs/bicycle.svg")),
+        Err(SpreetError::PathError(_))
+    );
+}
+
+#[test]
+fn raster_pixel_ratio_reads_file_name_suffix() {
+    assert_eq!(raster_pixel_ratio("icons/flag.png"), 1);
+    assert_eq!(raster_pixel_ratio("icons/flag@2x.png"), 2);
+    assert_eq!(raster_pixel_ratio("icons/flag@3x.webp"), 3);
+    assert_eq!(raster_pixel_ratio("icons/flag@0x.png"), 1);
+    assert_eq!(raster_pixel_ratio("icons/@2x.png"), 1);
+}
+
+#[test]
+fn load_index_reads_index_file() {
+    let index = load_index(Path::new("tests/fixtures/output/stretchable@2x.json")).unwrap();
+    let sprite = &index["cn-nths-expy-2-affinity"];
+    assert_eq!(sprite.pixel_ratio, 2);
+    assert!(sprite.content.is_some());
+    assert!(sprite.stretch_x.is_some());
+    assert!(sprite.stretch_y.is_some());
+}
+
+#[test]
+fn load_index_returns_error_for_invalid_json() {
+    assert_matches!(
+        load_index(Path::new("tests/fixtures/svgs/bicycle.svg")),
+        Err(SpreetError::JsonError(_))
+    );
+}
+
+#[test]
+fn write_atomically_replaces_file_without_leaving_temporary_file() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let path = temp.path().join("sprite.json");
+    std::fs::write(&path, "old").unwrap();
+
+    write_atomically(&path, b"new").unwrap();
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+    assert_eq!(std::fs::read_dir(temp.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn write_atomically_can_write_the_same_file_from_several_threads() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let path = temp.path().join("sprite.json");
+
+    std::thread::scope(|scope| {
+        let writers = (0..8)
+            .map(|_| scope.spawn(|| (0..20).all(|_| write_atomically(&path, b"new").is_ok())))
+            .collect::<Vec<_>>();
+        for writer in writers {
+            assert!(writer.join().unwrap());
+        }
+    });
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+    assert_eq!(std::fs::read_dir(temp.path()).unwrap().count(), 1);
+}
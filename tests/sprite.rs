@@ -1,8 +1,26 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use assert_matches::assert_matches;
 use resvg::usvg::{Options, Rect, Tree};
-use spreet::{load_svg, sprite_name, SpreetError, Sprite};
+use spreet::{
+    case_insensitive_name_collisions, check_sprite_names, get_svg_input_paths, load_raster,
+    load_svg, near_duplicate_sprites, ratio_file_prefix, sprite_name, validate_index, CrunchPacker,
+    IndexProblemKind, MaxRectsPacker, NameCollision, Packer, RenderCache, RenderOptions,
+    SdfOptions, ShelfPacker, SkylinePacker, SpreetError, Sprite, Spritesheet,
+};
+
+/// Load every SVG in `path` as a sprite at a pixel ratio of 1.
+fn load_sprites(path: &str, recursive: bool) -> BTreeMap<String, Sprite> {
+    get_svg_input_paths(path, recursive)
+        .unwrap()
+        .iter()
+        .map(|svg_path| {
+            let sprite = Sprite::new(load_svg(svg_path).unwrap(), 1).unwrap();
+            (sprite_name(svg_path, path).unwrap(), sprite)
+        })
+        .collect()
+}
 
 #[test]
 fn sprite_name_works_with_root_files() {
@@ -48,6 +66,51 @@ fn sprite_name_returns_ok_for_non_existent_path() {
     );
 }
 
+#[test]
+fn check_sprite_names_reports_every_collision() {
+    let images = [
+        ("park", "icons/park.svg"),
+        ("bench", "icons/bench.svg"),
+        ("park", "icons/park.png"),
+        ("bench", "more/bench.svg"),
+        ("shop", "more/shop.svg"),
+    ];
+    assert!(check_sprite_names(&images[..2]).is_ok());
+    assert_matches!(
+        check_sprite_names(&images),
+        Err(SpreetError::NameCollisionError(collisions)) if collisions == [
+            NameCollision {
+                names: vec![String::from("bench")],
+                paths: vec![PathBuf::from("icons/bench.svg"), PathBuf::from("more/bench.svg")],
+            },
+            NameCollision {
+                names: vec![String::from("park")],
+                paths: vec![PathBuf::from("icons/park.svg"), PathBuf::from("icons/park.png")],
+            },
+        ]
+    );
+}
+
+#[test]
+fn case_insensitive_name_collisions_finds_names_differing_only_by_case() {
+    let images = [
+        ("Park", "icons/Park.svg"),
+        ("park", "icons/park.svg"),
+        ("shop", "icons/shop.svg"),
+        ("shop", "more/shop.svg"),
+    ];
+    assert_eq!(
+        case_insensitive_name_collisions(&images),
+        [NameCollision {
+            names: vec![String::from("Park"), String::from("park")],
+            paths: vec![
+                PathBuf::from("icons/Park.svg"),
+                PathBuf::from("icons/park.svg")
+            ],
+        }]
+    );
+}
+
 #[test]
 fn sprite_name_returns_error_when_path_is_empty() {
     assert_matches!(
@@ -78,6 +141,278 @@ fn sprite_name_returns_error_when_base_path_not_parent_of_path() {
     );
 }
 
+#[test]
+fn sprite_name_removes_pixel_ratio_from_raster_files() {
+    let base_path = Path::new("./tests/fixtures/rasters");
+    assert_eq!(
+        sprite_name(base_path.join("iceland_flag@2x.png"), base_path).unwrap(),
+        "iceland_flag"
+    );
+    assert_eq!(
+        sprite_name(base_path.join("sweden_flag.webp"), base_path).unwrap(),
+        "sweden_flag"
+    );
+    assert_eq!(
+        sprite_name(
+            Path::new("./tests/fixtures/svgs/icon@2x.svg"),
+            "./tests/fixtures/svgs"
+        )
+        .unwrap(),
+        "icon@2x"
+    );
+}
+
+#[test]
+fn ratio_file_prefix_adds_suffix_for_retina_ratios() {
+    assert_eq!(ratio_file_prefix("sprite", 1), "sprite");
+    assert_eq!(ratio_file_prefix("sprite", 2), "sprite@2x");
+    assert_eq!(ratio_file_prefix("out/sprite", 3), "out/sprite@3x");
+}
+
+#[test]
+fn sprite_can_be_rendered_at_another_pixel_ratio() {
+    let path = Path::new("./tests/fixtures/stretchable/cn-nths-expy-2-affinity.svg");
+    let tree = load_svg(path).unwrap();
+    let sprite = Sprite::new(tree, 1).unwrap();
+    let retina_sprite = sprite.with_pixel_ratio(2).unwrap();
+
+    // The sprite is rendered again from the same SVG tree.
+    assert_eq!(retina_sprite.tree().size(), sprite.tree().size());
+    assert_eq!(retina_sprite.pixel_ratio(), 2);
+    assert_eq!(retina_sprite.pixmap().width(), sprite.pixmap().width() * 2);
+    assert_eq!(
+        retina_sprite.pixmap().height(),
+        sprite.pixmap().height() * 2
+    );
+    assert_eq!(
+        retina_sprite.content_area().unwrap(),
+        Rect::from_ltrb(4.0, 10.0, 36.0, 36.0).unwrap()
+    );
+}
+
+#[test]
+fn sprite_can_be_created_from_raster_image() {
+    let pixmap = load_raster("./tests/fixtures/rasters/iceland_flag@2x.png").unwrap();
+    let sprite = Sprite::from_pixmap(pixmap.clone(), 2, 1).unwrap();
+
+    assert!(sprite.svg_tree().is_none());
+    assert_eq!(sprite.pixel_ratio(), 1);
+    assert_eq!(sprite.pixmap().width(), 160);
+    assert_eq!(sprite.pixmap().height(), 115);
+    assert!(sprite.content_area().is_none());
+
+    let retina_sprite = sprite.with_pixel_ratio(2).unwrap();
+    assert_eq!(retina_sprite.pixmap(), &pixmap);
+}
+
+#[test]
+fn sprite_with_zero_pixel_ratio_is_an_error() {
+    let tree = load_svg("./tests/fixtures/svgs/bicycle.svg").unwrap();
+    assert_matches!(
+        Sprite::new(tree, 0).err(),
+        Some(SpreetError::ZeroSizedImageError)
+    );
+
+    let pixmap = load_raster("./tests/fixtures/rasters/iceland_flag@2x.png").unwrap();
+    assert_matches!(
+        Sprite::from_pixmap(pixmap.clone(), 0, 1).err(),
+        Some(SpreetError::PixelRatioError)
+    );
+    let sprite = Sprite::from_pixmap(pixmap, 2, 1).unwrap();
+    assert_matches!(
+        sprite.with_pixel_ratio(0).err(),
+        Some(SpreetError::ZeroSizedImageError)
+    );
+}
+
+#[test]
+fn sdf_sprite_can_be_created_from_raster_image() {
+    let pixmap = load_raster("./tests/fixtures/rasters/sweden_flag.webp").unwrap();
+    let sprite = Sprite::from_pixmap_sdf(pixmap, 1, 2).unwrap();
+
+    assert!(sprite.is_sdf());
+    assert_eq!(sprite.pixmap().width(), 320 * 2 + 2 * 6);
+    assert_eq!(sprite.pixmap().height(), 200 * 2 + 2 * 6);
+
+    let sprite = sprite.with_pixel_ratio(1).unwrap();
+    assert_eq!(sprite.pixmap().width(), 320 + 2 * 3);
+}
+
+#[test]
+fn sdf_sprite_can_use_custom_options() {
+    let path = Path::new("./tests/fixtures/svgs/circle.svg");
+    let sprite = Sprite::new_sdf(load_svg(path).unwrap(), 2).unwrap();
+    assert_eq!(sprite.sdf_buffer(), Some(6));
+
+    let options = SdfOptions {
+        buffer: 5,
+        radius: 4,
+        cutoff: 0.5,
+    };
+    let custom_sprite = Sprite::new_sdf_with_options(load_svg(path).unwrap(), 2, options).unwrap();
+    assert_eq!(custom_sprite.sdf_buffer(), Some(10));
+    assert_eq!(
+        custom_sprite.pixmap().width(),
+        sprite.pixmap().width() - 2 * 6 + 2 * 10
+    );
+
+    let sprite = custom_sprite.with_pixel_ratio(1).unwrap();
+    assert_eq!(sprite.sdf_buffer(), Some(5));
+    assert!(Sprite::new(load_svg(path).unwrap(), 1)
+        .unwrap()
+        .sdf_buffer()
+        .is_none());
+}
+
+#[test]
+fn render_cache_reuses_rendered_sprites() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let cache = RenderCache::new(temp.join("cache"));
+    let path = Path::new("./tests/fixtures/svgs/circle.svg");
+    let options = RenderOptions {
+        pixel_ratio: 2,
+        sdf: Some(SdfOptions::default()),
+        crop: true,
+        include_center: true,
+    };
+
+    // A newly rendered sprite is stored in the cache, and is the same as one rendered without it.
+    let rendered = cache.render(path, &options).unwrap();
+    let uncached = options.render(load_svg(path).unwrap()).unwrap();
+    assert_eq!(rendered.pixmap(), uncached.pixmap());
+    let entries = std::fs::read_dir(cache.dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(entries.len(), 1);
+
+    // The cached sprite is loaded instead of being rendered again.
+    let mut entry = std::fs::read(&entries[0]).unwrap();
+    *entry.last_mut().unwrap() ^= 1;
+    std::fs::write(&entries[0], &entry).unwrap();
+    let loaded = cache.render(path, &options).unwrap();
+    assert!(loaded.is_sdf());
+    assert_eq!(loaded.center().unwrap().x, uncached.center().unwrap().x);
+    assert_ne!(loaded.pixmap(), uncached.pixmap());
+
+    // Sprites rendered at another pixel ratio are cached separately.
+    let resampled = cache.with_pixel_ratio(&rendered, 1).unwrap();
+    assert_eq!(
+        resampled.pixmap(),
+        rendered.with_pixel_ratio(1).unwrap().pixmap()
+    );
+    assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 2);
+}
+
+#[test]
+fn render_cache_keeps_stretchable_icon_metadata() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let cache = RenderCache::new(temp.join("cache"));
+    let path = Path::new("./tests/fixtures/stretchable/cn-nths-expy-2-inkscape-plain.svg");
+    let options = RenderOptions {
+        pixel_ratio: 2,
+        sdf: None,
+        crop: false,
+        include_center: false,
+    };
+
+    let rendered = cache.render(path, &options).unwrap();
+    let loaded = cache.render(path, &options).unwrap();
+    assert!(rendered.svg_tree().is_some());
+    assert!(loaded.svg_tree().is_none());
+    assert_eq!(loaded.pixmap(), rendered.pixmap());
+    assert_eq!(loaded.content_area(), rendered.content_area());
+    assert_eq!(loaded.stretch_x_areas(), rendered.stretch_x_areas());
+    assert_eq!(loaded.stretch_y_areas(), rendered.stretch_y_areas());
+    assert!(loaded.stretch_x_areas().is_some());
+
+    // A sprite loaded from the cache can still be rendered at another pixel ratio.
+    let resampled = cache.with_pixel_ratio(&loaded, 1).unwrap();
+    assert_eq!(
+        resampled.pixmap(),
+        rendered.with_pixel_ratio(1).unwrap().pixmap()
+    );
+}
+
+#[test]
+fn render_cache_renders_changed_images_again() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let cache = RenderCache::new(temp.join("cache"));
+    let path = temp.join("icon.svg");
+    let options = RenderOptions {
+        pixel_ratio: 1,
+        sdf: None,
+        crop: false,
+        include_center: false,
+    };
+
+    std::fs::copy("./tests/fixtures/svgs/circle.svg", &path).unwrap();
+    let circle = cache.render(&path, &options).unwrap();
+    std::fs::copy("./tests/fixtures/svgs/bicycle.svg", &path).unwrap();
+    let bicycle = cache.render(&path, &options).unwrap();
+    assert_ne!(circle.pixmap(), bicycle.pixmap());
+    assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 2);
+}
+
+#[test]
+fn render_cache_can_render_the_same_image_from_several_threads() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let cache = RenderCache::new(temp.join("cache"));
+    let options = RenderOptions {
+        pixel_ratio: 2,
+        sdf: None,
+        crop: false,
+        include_center: false,
+    };
+    let paths = (0..16)
+        .map(|i| {
+            let path = temp.join(format!("circle-{i}.svg"));
+            std::fs::copy("./tests/fixtures/svgs/circle.svg", &path).unwrap();
+            path
+        })
+        .collect::<Vec<_>>();
+
+    let (cache, options) = (&cache, &options);
+    let sprites = std::thread::scope(|scope| {
+        let threads = paths
+            .iter()
+            .map(|path| scope.spawn(move || cache.render(path, options)))
+            .collect::<Vec<_>>();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert!(sprites
+        .iter()
+        .all(|sprite| sprite.pixmap() == sprites[0].pixmap()));
+    assert!(cache.take_store_errors().is_empty());
+    assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 1);
+}
+
+#[test]
+fn render_cache_returns_sprites_that_cannot_be_stored() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    // The cache directory can't be created, because there's a file in its place.
+    std::fs::write(temp.join("cache"), "").unwrap();
+    let cache = RenderCache::new(temp.join("cache"));
+    let path = Path::new("./tests/fixtures/svgs/circle.svg");
+    let options = RenderOptions {
+        pixel_ratio: 1,
+        sdf: None,
+        crop: false,
+        include_center: false,
+    };
+
+    let sprite = cache.render(path, &options).unwrap();
+    assert_eq!(
+        sprite.pixmap(),
+        options.render(load_svg(path).unwrap()).unwrap().pixmap()
+    );
+    assert_eq!(cache.take_store_errors().len(), 1);
+    assert!(cache.take_store_errors().is_empty());
+}
+
 #[test]
 fn unstretchable_icon_has_no_metadata() {
     let path = Path::new("./tests/fixtures/svgs/bicycle.svg");
@@ -191,3 +526,498 @@ fn stretchable_icon_with_metadata_in_hidden_element_is_ignored() {
 
     assert!(sprite.content_area().is_none());
 }
+
+#[test]
+fn spritesheet_keeps_previous_positions() {
+    let mut builder = Spritesheet::build();
+    builder.sprites(load_sprites("./tests/fixtures/svgs", false));
+    let previous = builder.generate().unwrap();
+
+    // Adding a new sprite doesn't move the existing ones.
+    let mut builder = Spritesheet::build();
+    builder
+        .sprites(load_sprites("./tests/fixtures/svgs", true))
+        .previous_index(previous.get_index().clone());
+    let spritesheet = builder.generate().unwrap();
+
+    let index = spritesheet.get_index();
+    assert_eq!(index.len(), previous.get_index().len() + 1);
+    for (name, description) in previous.get_index() {
+        assert_eq!(
+            (index[name].x, index[name].y),
+            (description.x, description.y)
+        );
+    }
+    let bear = &index["recursive/bear"];
+    for (name, description) in previous.get_index() {
+        let overlaps = bear.x < description.x + description.width
+            && description.x < bear.x + bear.width
+            && bear.y < description.y + description.height
+            && description.y < bear.y + bear.height;
+        assert!(!overlaps, "bear overlaps {name}");
+    }
+}
+
+#[test]
+fn spritesheet_can_be_split_into_pages() {
+    let mut builder = Spritesheet::build();
+    builder
+        .sprites(load_sprites("./tests/fixtures/svgs", true))
+        .max_size(32);
+    let pages = builder.clone().generate_pages().unwrap();
+
+    assert!(pages.len() > 1);
+    for page in &pages {
+        assert!(page.pixmap().width() <= 32);
+        assert!(page.pixmap().height() <= 32);
+    }
+    let index = Spritesheet::combined_index(&pages);
+    assert_eq!(index.len(), 4);
+    for (page, spritesheet) in pages.iter().enumerate() {
+        for name in spritesheet.get_index().keys() {
+            assert_eq!(index[name].page, Some(page));
+        }
+    }
+
+    // The sprites don't fit in a single spritesheet of that size.
+    assert_matches!(
+        builder.generate().err(),
+        Some(SpreetError::PackingError {
+            width: 32,
+            height: 32
+        })
+    );
+}
+
+#[test]
+fn spritesheet_pages_have_the_same_sprites_at_every_pixel_ratio() {
+    let mut builder = Spritesheet::build();
+    builder
+        .sprites(load_sprites("./tests/fixtures/svgs", true))
+        .max_size(48);
+    let ratio_pages = builder.generate_ratio_pages(&[1, 2]).unwrap();
+
+    // The sprites fit on one page at a pixel ratio of 1, but not at 2.
+    assert!(ratio_pages[&2].len() > 1);
+    let page_names = |ratio| {
+        ratio_pages[&ratio]
+            .iter()
+            .map(|page| page.get_index().keys().collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(page_names(1), page_names(2));
+}
+
+#[test]
+fn spritesheet_without_sprites_is_an_error() {
+    let mut builder = Spritesheet::build();
+    builder.sprites(BTreeMap::new());
+
+    assert_matches!(
+        builder.generate().err(),
+        Some(SpreetError::EmptySpritesheetError)
+    );
+}
+
+#[test]
+fn spritesheet_has_one_page_without_max_size() {
+    let mut builder = Spritesheet::build();
+    builder.sprites(load_sprites("./tests/fixtures/svgs", true));
+    let pages = builder.generate_pages().unwrap();
+
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].get_index().len(), 4);
+    assert!(pages[0].get_index().values().all(|d| d.page.is_none()));
+}
+
+#[test]
+fn spritesheet_pages_reject_sprites_larger_than_max_size() {
+    let mut builder = Spritesheet::build();
+    builder
+        .sprites(load_sprites("./tests/fixtures/svgs", true))
+        .max_size(16);
+
+    assert_matches!(
+        builder.generate_pages().err(),
+        Some(SpreetError::PackingError {
+            width: 16,
+            height: 16
+        })
+    );
+}
+
+#[test]
+fn spritesheet_can_be_loaded() {
+    let spritesheet = Spritesheet::load(
+        "./tests/fixtures/output/default@1x.png",
+        "./tests/fixtures/output/default@1x.json",
+    )
+    .unwrap();
+
+    let index = spritesheet.get_index();
+    assert_eq!(index.len(), 3);
+    assert_eq!(index["bicycle"].pixel_ratio, 1);
+    for description in index.values() {
+        assert!(description.x + description.width <= spritesheet.pixmap().width());
+        assert!(description.y + description.height <= spritesheet.pixmap().height());
+    }
+}
+
+#[test]
+fn spritesheet_load_round_trips_index() {
+    let spritesheet = Spritesheet::load(
+        "./tests/fixtures/output/sdf@2x.png",
+        "./tests/fixtures/output/sdf@2x.json",
+    )
+    .unwrap();
+
+    let expected = std::fs::read_to_string("./tests/fixtures/output/sdf@2x.json").unwrap();
+    let actual = serde_json::to_string_pretty(spritesheet.get_index()).unwrap();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn spritesheet_can_crop_sprite_pixmap() {
+    let spritesheet = Spritesheet::load(
+        "./tests/fixtures/output/default@2x.png",
+        "./tests/fixtures/output/default@2x.json",
+    )
+    .unwrap();
+
+    let description = &spritesheet.get_index()["circle"];
+    let pixmap = spritesheet.sprite_pixmap("circle").unwrap();
+    assert_eq!(pixmap.width(), description.width);
+    assert_eq!(pixmap.height(), description.height);
+    assert!(spritesheet.sprite_pixmap("does_not_exist").is_none());
+}
+
+#[test]
+fn spritesheet_can_be_sliced_into_sprites() {
+    let spritesheet = Spritesheet::load(
+        "./tests/fixtures/output/stretchable@2x.png",
+        "./tests/fixtures/output/stretchable@2x.json",
+    )
+    .unwrap();
+    let sprites = spritesheet.sprites().unwrap();
+    let sprite = &sprites["cn-nths-expy-2-affinity"];
+
+    assert!(sprite.svg_tree().is_none());
+    assert_eq!(sprite.pixel_ratio(), 2);
+    assert_eq!(
+        sprite.content_area().unwrap(),
+        Rect::from_ltrb(4.0, 10.0, 36.0, 36.0).unwrap()
+    );
+
+    // Sliced sprites are resampled rather than rendered again.
+    let sprite = sprite.with_pixel_ratio(1).unwrap();
+    assert_eq!(sprite.pixmap().width(), 20);
+    assert_eq!(
+        sprite.content_area().unwrap(),
+        Rect::from_ltrb(2.0, 5.0, 18.0, 18.0).unwrap()
+    );
+    assert_eq!(
+        sprite.stretch_x_areas().unwrap(),
+        [Rect::from_ltrb(4.0, 0.0, 16.0, 0.0).unwrap()]
+    );
+}
+
+#[test]
+fn sliced_sprite_center_is_scaled_from_its_own_pixel_ratio() {
+    let mut sprite = Sprite::new(load_svg("./tests/fixtures/svgs/circle.svg").unwrap(), 2).unwrap();
+    sprite.crop(true);
+    let center = sprite.center().unwrap();
+    let mut builder = Spritesheet::build();
+    builder.sprites(BTreeMap::from([("circle".to_string(), sprite)]));
+    let sprites = builder.generate().unwrap().sprites().unwrap();
+
+    // Each copy's center is scaled from the sprite it was made from, not from the spritesheet.
+    let half = sprites["circle"].with_pixel_ratio(1).unwrap();
+    let double = half.with_pixel_ratio(4).unwrap();
+    assert_eq!(half.center().unwrap().x, center.x / 2.0);
+    assert_eq!(half.center().unwrap().y, center.y / 2.0);
+    assert_eq!(double.center().unwrap().x, center.x * 2.0);
+    assert_eq!(double.center().unwrap().y, center.y * 2.0);
+}
+
+#[test]
+fn sliced_sprites_can_be_packed_into_a_new_spritesheet() {
+    let original = Spritesheet::load(
+        "./tests/fixtures/output/default@1x.png",
+        "./tests/fixtures/output/default@1x.json",
+    )
+    .unwrap();
+    let mut builder = Spritesheet::build();
+    builder.sprites(original.sprites().unwrap());
+    let spritesheet = builder.generate().unwrap();
+
+    assert_eq!(spritesheet.pixmap().data(), original.pixmap().data());
+    for (name, description) in original.get_index() {
+        let new_description = &spritesheet.get_index()[name];
+        assert_eq!(
+            (new_description.x, new_description.y),
+            (description.x, description.y)
+        );
+    }
+}
+
+#[test]
+fn spritesheet_decode_returns_error_when_sprite_outside_image() {
+    let png = std::fs::read("./tests/fixtures/output/default@1x.png").unwrap();
+    let index = br#"{"huge": {"height": 10000, "pixelRatio": 1, "width": 1, "x": 0, "y": 0}}"#;
+
+    assert_matches!(
+        Spritesheet::decode(&png, index).err(),
+        Some(SpreetError::SpriteBoundsError(name)) if name == "huge"
+    );
+}
+
+#[test]
+fn spritesheet_decode_returns_error_for_invalid_png() {
+    let index = std::fs::read("./tests/fixtures/output/default@1x.json").unwrap();
+
+    assert_matches!(
+        Spritesheet::decode(b"not a png", &index).err(),
+        Some(SpreetError::PngDecodingError(_))
+    );
+}
+
+/// Assert that every sprite in a spritesheet lies within it and doesn't overlap another sprite.
+fn assert_sprites_do_not_overlap(spritesheet: &Spritesheet) {
+    let rects = spritesheet
+        .get_index()
+        .values()
+        .map(|desc| (desc.x, desc.y, desc.width, desc.height))
+        .collect::<Vec<_>>();
+    for (i, &(x, y, w, h)) in rects.iter().enumerate() {
+        assert!(x + w <= spritesheet.pixmap().width());
+        assert!(y + h <= spritesheet.pixmap().height());
+        for &(x2, y2, w2, h2) in &rects[i + 1..] {
+            assert!(x + w <= x2 || x2 + w2 <= x || y + h <= y2 || y2 + h2 <= y);
+        }
+    }
+}
+
+/// Pack the stretchable fixtures with `packer`, with spacing between the sprites.
+fn pack_with<P: Packer + Send + Sync + 'static>(packer: P) -> Spritesheet {
+    let mut builder = Spritesheet::build();
+    builder
+        .sprites(load_sprites("./tests/fixtures/stretchable", false))
+        .spacing(2)
+        .packer(packer);
+    builder.generate().unwrap()
+}
+
+#[test]
+fn spritesheet_can_use_each_packer() {
+    for spritesheet in [
+        pack_with(CrunchPacker),
+        pack_with(ShelfPacker),
+        pack_with(SkylinePacker),
+        pack_with(MaxRectsPacker),
+    ] {
+        assert_eq!(spritesheet.get_index().len(), 8);
+        assert_sprites_do_not_overlap(&spritesheet);
+        assert!(spritesheet.fill_ratio() > 0.0 && spritesheet.fill_ratio() <= 1.0);
+    }
+}
+
+#[test]
+fn spritesheet_can_have_any_size() {
+    let mut builder = Spritesheet::build();
+    builder
+        .sprites(load_sprites("./tests/fixtures/stretchable", false))
+        .spacing(2);
+    let po2 = builder.clone().generate().unwrap();
+    builder.allow_any_size();
+    let any_size = builder.generate().unwrap();
+
+    assert_eq!(any_size.get_index().len(), 8);
+    assert_sprites_do_not_overlap(&any_size);
+    assert!(any_size.fill_ratio() > po2.fill_ratio());
+}
+
+#[test]
+fn spritesheet_can_be_square() {
+    let mut builder = Spritesheet::build();
+    builder
+        .sprites(load_sprites("./tests/fixtures/stretchable", false))
+        .packer(SkylinePacker)
+        .make_square();
+    let spritesheet = builder.generate().unwrap();
+
+    assert_eq!(spritesheet.pixmap().width(), spritesheet.pixmap().height());
+    assert_sprites_do_not_overlap(&spritesheet);
+}
+
+#[test]
+fn spritesheet_can_have_fixed_width() {
+    let mut builder = Spritesheet::build();
+    builder
+        .sprites(load_sprites("./tests/fixtures/stretchable", false))
+        .spacing(2)
+        .packer(ShelfPacker)
+        .fixed_width(50);
+    let spritesheet = builder.clone().generate().unwrap();
+
+    assert_eq!(spritesheet.pixmap().width(), 50);
+    assert_eq!(spritesheet.get_index().len(), 8);
+    assert_sprites_do_not_overlap(&spritesheet);
+
+    // The widest sprite doesn't fit within 10 pixels.
+    builder.fixed_width(10);
+    assert_matches!(
+        builder.generate().err(),
+        Some(SpreetError::PackingError { width: 10, .. })
+    );
+}
+
+#[test]
+fn spritesheet_report_lists_every_sprite() {
+    let mut builder = Spritesheet::build();
+    builder
+        .sprites(load_sprites("./tests/fixtures/svgs", false))
+        .make_unique();
+    let spritesheet = builder.generate().unwrap();
+
+    let html = spritesheet.html_report("Icons <light>").unwrap();
+    assert!(html.contains("<title>Icons &lt;light&gt;</title>"));
+    assert_eq!(html.matches("<img src=\"data:image/png;base64,").count(), 3);
+    assert!(html.contains("<td>bicycle</td><td>15×15</td><td>1</td><td>another_bicycle</td>"));
+    assert!(html.contains("<td>circle</td><td>20×20</td><td>1</td><td></td>"));
+
+    let markdown = spritesheet.markdown_report("Icons").unwrap();
+    assert!(markdown.starts_with("# Icons\n\n3 sprites in a spritesheet of"));
+    assert_eq!(markdown.matches("| ![](data:image/png;base64,").count(), 3);
+    assert!(markdown.contains(" | another\\_bicycle | 15×15 | 1 | bicycle |"));
+}
+
+#[test]
+fn spritesheet_report_includes_stretch_metadata() {
+    let mut builder = Spritesheet::build();
+    builder.sprites(load_sprites("./tests/fixtures/stretchable", false));
+    let spritesheet = builder.generate().unwrap();
+
+    let markdown = spritesheet.markdown_report("Stretchable").unwrap();
+    assert!(markdown.contains(
+        " | cn-nths-expy-2-affinity | 20×23 | 1 |  |  | [4, 16] | [5, 16] | [2, 5, 18, 18] |"
+    ));
+}
+
+#[test]
+fn spritesheet_diff_finds_changed_sprites() {
+    let old_sprites = load_sprites("./tests/fixtures/svgs", false);
+    let mut new_sprites = old_sprites.clone();
+    // Rename a sprite, resize another, and change a pixel of the third.
+    let bicycle = new_sprites.remove("bicycle").unwrap();
+    new_sprites.insert("bike".to_string(), bicycle);
+    let stretchable = load_sprites("./tests/fixtures/stretchable", false);
+    new_sprites.insert(
+        "another_bicycle".to_string(),
+        stretchable["cn-nths-expy-2-affinity"].clone(),
+    );
+    let mut circle = new_sprites["circle"].pixmap().clone();
+    circle.data_mut()[..4].copy_from_slice(&[255, 255, 255, 255]);
+    new_sprites.insert(
+        "circle".to_string(),
+        Sprite::from_pixmap(circle, 1, 1).unwrap(),
+    );
+    let mut builder = Spritesheet::build();
+    let old = builder.sprites(old_sprites).clone().generate().unwrap();
+    let new = builder.sprites(new_sprites).clone().generate().unwrap();
+
+    let diff = old.diff(&new);
+    assert!(diff.added.is_empty());
+    assert!(diff.removed.is_empty());
+    assert_eq!(diff.renamed, [("bicycle".to_string(), "bike".to_string())]);
+    assert_eq!(diff.resized.len(), 1);
+    assert_eq!(diff.resized[0].name, "another_bicycle");
+    assert_eq!(diff.resized[0].old_size, (15, 15));
+    assert_eq!(diff.resized[0].new_size, (20, 23));
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].name, "circle");
+    assert!(diff.changed[0].difference > 0.0 && diff.changed[0].difference < 0.01);
+
+    // The image has a row for the resized sprite and a row for the changed sprite.
+    let image = diff.image(&old, &new).unwrap();
+    assert_eq!((image.width(), image.height()), (76, 55));
+
+    let diff = old.diff(&old);
+    assert!(diff.is_empty());
+    assert!(diff.image(&old, &old).is_none());
+}
+
+#[test]
+fn near_duplicate_sprites_differ_by_a_few_pixels() {
+    let mut sprites = load_sprites("./tests/fixtures/svgs", false);
+    let circle = sprites["circle"].clone();
+    let mut pixmap = circle.pixmap().clone();
+    pixmap.data_mut()[..8].copy_from_slice(&[255; 8]);
+    sprites.insert("circle_copy".to_string(), circle);
+    sprites.insert(
+        "circle_edited".to_string(),
+        Sprite::from_pixmap(pixmap, 1, 1).unwrap(),
+    );
+
+    // Identical sprites, and sprites of different sizes, aren't near duplicates.
+    let near_duplicates = near_duplicate_sprites(&sprites, 0.01);
+    let pairs = near_duplicates
+        .iter()
+        .map(|pair| (pair.first.as_str(), pair.second.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        pairs,
+        [
+            ("circle", "circle_edited"),
+            ("circle_copy", "circle_edited")
+        ]
+    );
+    assert_eq!(near_duplicates[0].differing_pixels, 2);
+    assert!(near_duplicates[0].difference > 0.0 && near_duplicates[0].difference < 0.01);
+
+    assert!(near_duplicate_sprites(&sprites, 0.0).is_empty());
+
+    // The threshold is inclusive, and sprites that are only just over it aren't reported.
+    let difference = near_duplicates[0].difference;
+    assert_eq!(near_duplicate_sprites(&sprites, difference).len(), 2);
+    assert!(near_duplicate_sprites(&sprites, difference * 0.99).is_empty());
+}
+
+#[test]
+fn validate_index_reports_every_problem() {
+    let index = br#"{
+        "fine": {"x": 0, "y": 0, "width": 20, "height": 20, "pixelRatio": 2,
+                 "stretchX": [[2, 8], [10, 18]], "content": [2, 2, 18, 18]},
+        "outside": {"x": 10, "y": 0, "width": 20, "height": 20, "pixelRatio": 2},
+        "stretchy": {"x": 0, "y": 0, "width": 20, "height": 20, "pixelRatio": 2,
+                     "stretchX": [[10, 18], [2, 8]], "stretchY": [[5, 25], [12, 6]]},
+        "content": {"x": 0, "y": 0, "width": 20, "height": 20, "pixelRatio": 2,
+                    "content": [18, -2, 2, 18]},
+        "ratio": {"x": 0, "y": 0, "width": 20, "height": 20},
+        "sdf": {"x": 0, "y": 0, "width": 20, "height": 20, "pixelRatio": 2, "sdf": true},
+        "invalid": {"x": 0, "y": 0, "width": "wide", "height": 20}
+    }"#;
+    let problems = validate_index(index, 20, 20, 2).unwrap();
+    let problems = problems
+        .iter()
+        .map(|problem| (problem.sprite.as_str(), problem.kind))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        problems,
+        [
+            ("content", IndexProblemKind::ContentUnordered),
+            ("content", IndexProblemKind::ContentOutsideSprite),
+            ("invalid", IndexProblemKind::InvalidDescription),
+            ("outside", IndexProblemKind::OutsideImage),
+            ("ratio", IndexProblemKind::PixelRatioMismatch),
+            ("sdf", IndexProblemKind::InconsistentSdf),
+            ("stretchy", IndexProblemKind::StretchXUnordered),
+            ("stretchy", IndexProblemKind::StretchYOutsideSprite),
+            ("stretchy", IndexProblemKind::StretchYUnordered),
+        ]
+    );
+    assert_matches!(
+        validate_index(b"[]", 20, 20, 2),
+        Err(SpreetError::JsonError(_))
+    );
+}
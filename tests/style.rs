@@ -0,0 +1,80 @@
+use std::collections::BTreeSet;
+
+use serde_json::json;
+use spreet::{load_style, style_icon_references, IconReference};
+
+#[test]
+fn style_icon_references_finds_names_in_expressions() {
+    let style = load_style("tests/fixtures/styles/style.json").unwrap();
+    let references = style_icon_references(&style);
+
+    let layers = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<BTreeSet<_>>();
+    assert_eq!(
+        references.into_iter().collect::<Vec<_>>(),
+        [
+            (
+                IconReference::Name("bicycle".to_string()),
+                layers(&["cycleways"])
+            ),
+            (
+                IconReference::Name("circle".to_string()),
+                layers(&["background"])
+            ),
+            (
+                IconReference::Name("road".to_string()),
+                layers(&["cycleways"])
+            ),
+            (IconReference::Name("shop".to_string()), layers(&["shops"])),
+            (
+                IconReference::Runtime("shop-{kind}".to_string()),
+                layers(&["shops"])
+            ),
+            (
+                IconReference::Runtime("{class}_bicycle".to_string()),
+                layers(&["labels"])
+            ),
+        ]
+    );
+}
+
+#[test]
+fn style_icon_references_reads_legacy_functions_and_step_expressions() {
+    let style = json!({
+        "layers": [
+            {
+                "id": "zoomed",
+                "layout": {"icon-image": {"stops": [[10, "small"], [14, "large"]], "default": ""}}
+            },
+            {
+                "id": "stepped",
+                "layout": {"icon-image": ["step", ["zoom"], "dot", 12, ["case", ["has", "name"], "pin", "x"]]}
+            },
+            {"id": "text", "layout": {"text-field": "not-an-icon"}}
+        ]
+    });
+    let names = style_icon_references(&style)
+        .into_keys()
+        .map(|reference| reference.to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(names, ["dot", "large", "pin", "small", "x"]);
+}
+
+#[test]
+fn icon_reference_matches_sprite_names() {
+    let name = IconReference::Name("airport-15".to_string());
+    assert!(name.matches("airport-15"));
+    assert!(!name.matches("airport"));
+
+    let template = IconReference::Runtime("{class}-15".to_string());
+    assert!(template.matches("airport-15"));
+    assert!(template.matches("-15"));
+    assert!(!template.matches("airport-11"));
+
+    let template = IconReference::Runtime("shop-{kind}-{size}x".to_string());
+    assert!(template.matches("shop-bakery-2x"));
+    assert!(!template.matches("shop-bakery"));
+    assert!(!template.matches("bakery-2x"));
+
+    assert!(IconReference::Runtime("{?}".to_string()).matches("anything"));
+}